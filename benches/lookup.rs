@@ -0,0 +1,43 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use wad::{parse_wad, EntryId, Kind, Wad, WadBuilder};
+
+/// A WAD with a directory the size of DOOM2.WAD's, mostly short lumps with
+/// distinct names
+fn doom2_sized_wad() -> Wad {
+    let mut builder = WadBuilder::new(Kind::IWad);
+    for i in 0..2919 {
+        let id = EntryId::from_str(format!("L{:07}", i)).unwrap();
+        builder.push(id, vec![0; 4]);
+    }
+    parse_wad(builder.to_bytes().unwrap()).unwrap()
+}
+
+fn lookup(c: &mut Criterion) {
+    let wad = doom2_sized_wad();
+    let ids: Vec<_> = (0..2919)
+        .step_by(7)
+        .map(|i| wad.entry_id(i).unwrap())
+        .collect();
+
+    c.bench_function("index_of", |b| {
+        b.iter(|| {
+            for &id in &ids {
+                black_box(wad.index_of(id));
+            }
+        })
+    });
+
+    let index = wad.build_index();
+    c.bench_function("WadIndex::index_of", |b| {
+        b.iter(|| {
+            for &id in &ids {
+                black_box(index.index_of(id));
+            }
+        })
+    });
+
+    c.bench_function("build_index", |b| b.iter(|| black_box(wad.build_index())));
+}
+
+criterion_group!(benches, lookup);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+extern crate wad;
+
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+use wad::Severity;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "wad-check",
+    about = "Check a WAD file for structural problems. Exits with a non-zero status if any errors are found."
+)]
+struct Opt {
+    /// Input WAD file
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+
+    /// Also exit with a non-zero status if any warnings are found
+    #[structopt(long = "deny-warnings")]
+    deny_warnings: bool,
+
+    /// Don't report informational findings, such as unused space
+    #[structopt(short = "q", long = "quiet")]
+    quiet: bool,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let opt = Opt::from_args();
+
+    let wad = wad::load_wad_file(opt.input).map_err(|e| e.to_string())?;
+
+    let fail_at = if opt.deny_warnings {
+        Severity::Warning
+    } else {
+        Severity::Error
+    };
+
+    let mut failed = false;
+    for finding in wad.validate() {
+        if opt.quiet && finding.severity == Severity::Info {
+            continue;
+        }
+        println!("{}", finding);
+        failed |= finding.severity >= fail_at;
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
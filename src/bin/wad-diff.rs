@@ -0,0 +1,127 @@
+extern crate wad;
+
+mod common;
+
+use std::path::PathBuf;
+
+use common::json_string;
+use structopt::clap::ErrorKind;
+use structopt::StructOpt;
+use wad::{Change, EntryId};
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "wad-diff",
+    about = "Compare the lumps in two WAD files. Exits with status 1 if they differ, and 2 on errors."
+)]
+struct Opt {
+    /// Old WAD file
+    #[structopt(parse(from_os_str))]
+    old: PathBuf,
+
+    /// New WAD file
+    #[structopt(parse(from_os_str))]
+    new: PathBuf,
+
+    /// Output the changes as a JSON array
+    #[structopt(long = "json")]
+    json: bool,
+}
+
+fn name(id: &EntryId) -> String {
+    json_string(&id.escaped())
+}
+
+fn to_json(change: &Change) -> String {
+    match change {
+        Change::Added { index, id } => format!(
+            r#"{{"change":"added","index":{},"name":{}}}"#,
+            index,
+            name(id)
+        ),
+        Change::Removed { index, id } => format!(
+            r#"{{"change":"removed","index":{},"name":{}}}"#,
+            index,
+            name(id)
+        ),
+        Change::Renamed {
+            old_index,
+            new_index,
+            old_id,
+            new_id,
+        } => format!(
+            r#"{{"change":"renamed","old_index":{},"new_index":{},"old_name":{},"new_name":{}}}"#,
+            old_index,
+            new_index,
+            name(old_id),
+            name(new_id)
+        ),
+        Change::Moved {
+            old_index,
+            new_index,
+            id,
+        } => format!(
+            r#"{{"change":"moved","old_index":{},"new_index":{},"name":{}}}"#,
+            old_index,
+            new_index,
+            name(id)
+        ),
+        Change::Modified {
+            old_index,
+            new_index,
+            id,
+            old_length,
+            new_length,
+        } => format!(
+            r#"{{"change":"modified","old_index":{},"new_index":{},"name":{},"old_length":{},"new_length":{}}}"#,
+            old_index,
+            new_index,
+            name(id),
+            old_length,
+            new_length
+        ),
+    }
+}
+
+/// Whether the WADs differ
+fn run(opt: Opt) -> Result<bool, Box<dyn std::error::Error>> {
+    let old = wad::load_wad_file(opt.old).map_err(|e| e.to_string())?;
+    let new = wad::load_wad_file(opt.new).map_err(|e| e.to_string())?;
+
+    let changes = wad::diff(&old.as_slice(), &new.as_slice()).map_err(|e| e.to_string())?;
+
+    if opt.json {
+        let changes: Vec<_> = changes.iter().map(to_json).collect();
+        println!("[{}]", changes.join(","));
+    } else {
+        for change in &changes {
+            println!("{}", change);
+        }
+    }
+
+    Ok(!changes.is_empty())
+}
+
+fn main() {
+    // Status 1 means that the WADs differ, so errors exit with 2, like
+    // diff(1) and cmp(1)
+    let opt = match Opt::from_iter_safe(std::env::args_os()) {
+        Ok(opt) => opt,
+        Err(e) => match e.kind {
+            ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => e.exit(),
+            _ => {
+                eprintln!("{}", e.message);
+                std::process::exit(2);
+            }
+        },
+    };
+
+    match run(opt) {
+        Ok(false) => (),
+        Ok(true) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+extern crate wad;
+
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+use wad::{Manifest, WadInfo, MANIFEST_FILE_NAME, WADINFO_FILE_NAME};
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "wad-extract",
+    about = "Extract all lumps in a WAD file to a directory, along with a manifest listing the entries in order"
+)]
+struct Opt {
+    /// Input WAD file
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+
+    /// Output directory. Created if it does not exist.
+    #[structopt(parse(from_os_str))]
+    output: PathBuf,
+
+    /// Also write a DeuTex wadinfo.txt describing the entries
+    #[structopt(long = "wadinfo")]
+    wadinfo: bool,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let opt = Opt::from_args();
+
+    let wad = wad::load_wad_file(&opt.input).map_err(|e| e.to_string())?;
+    let manifest = Manifest::new(wad.kind(), &wad.as_slice()).map_err(|e| e.to_string())?;
+
+    std::fs::create_dir_all(&opt.output)?;
+
+    for (index, entry) in manifest.entries.iter().enumerate() {
+        if let Some(path) = &entry.path {
+            let path = opt.output.join(path);
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(&path, wad.lump(index)?)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+    }
+
+    std::fs::write(opt.output.join(MANIFEST_FILE_NAME), manifest.to_string())?;
+
+    if opt.wadinfo {
+        let wadinfo = WadInfo::from_wad(&wad.as_slice());
+        std::fs::write(opt.output.join(WADINFO_FILE_NAME), wadinfo.to_string())?;
+    }
+
+    Ok(())
+}
@@ -1,8 +1,68 @@
 extern crate wad;
 
+mod common;
+
 use std::path::PathBuf;
 
+use common::json_string;
 use structopt::StructOpt;
+use wad::{ContentType, EntryIdPattern, Namespace, ParseOptions};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Table,
+    Csv,
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "table" => Ok(Format::Table),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Invalid format: {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Offset,
+    Raw,
+    Namespace,
+    Type,
+    Hash,
+}
+
+impl Column {
+    fn name(self) -> &'static str {
+        match self {
+            Column::Offset => "offset",
+            Column::Raw => "raw",
+            Column::Namespace => "namespace",
+            Column::Type => "type",
+            Column::Hash => "hash",
+        }
+    }
+}
+
+impl std::str::FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Column, String> {
+        match s {
+            "offset" => Ok(Column::Offset),
+            "raw" => Ok(Column::Raw),
+            "namespace" => Ok(Column::Namespace),
+            "type" => Ok(Column::Type),
+            "hash" => Ok(Column::Hash),
+            _ => Err(format!("Invalid column: {:?}", s)),
+        }
+    }
+}
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "wad-ls", about = "List the lumps in a WAD file")]
@@ -10,15 +70,216 @@ struct Opt {
     /// Input WAD file
     #[structopt(parse(from_os_str))]
     input: PathBuf,
+
+    /// Only list lumps with names matching this pattern. Use * to match any
+    /// number of characters and ? to match exactly one, for example "DS*" or
+    /// "E?M?". Matching is case insensitive.
+    pattern: Option<String>,
+
+    /// Salvage what can be read from a damaged WAD file instead of
+    /// rejecting it, and report what was fixed
+    #[structopt(long = "recover")]
+    recover: bool,
+
+    /// Output format: table, csv or json. The table is tab-separated, with a
+    /// comment line naming the game, when it can be told from the lump
+    /// names. json writes one object per lump.
+    #[structopt(long = "format", default_value = "table")]
+    format: Format,
+
+    /// Identify the exact release of the WAD by its MD5 digest for the
+    /// comment line of the table. This reads the whole file. Ignored with
+    /// --recover, since the data may have been changed.
+    #[structopt(long = "identify")]
+    identify: bool,
+
+    /// Extra columns, separated by commas. offset: the position of the lump
+    /// in the file. raw: the name as 16 hex digits. namespace: the enclosing
+    /// namespaces, such as F/F1. type: the detected content type. hash: the
+    /// MD5 digest of the lump.
+    #[structopt(long = "columns", raw(use_delimiter = "true"))]
+    columns: Vec<Column>,
+
+    /// Only list lumps within this namespace, for example S for sprites.
+    /// Nested namespaces are included, but the markers are not.
+    #[structopt(long = "namespace")]
+    namespace: Option<String>,
+}
+
+/// The names of the namespaces each entry is in, outermost first. Markers
+/// belong to the namespace they delimit.
+fn assign_namespaces(namespaces: &[Namespace], parent: &[String], names: &mut [Vec<String>]) {
+    for namespace in namespaces {
+        let mut path = parent.to_vec();
+        path.push(namespace.name.clone());
+
+        let end = match namespace.end {
+            Some(end) => end + 1,
+            None => namespace.entries_start() + namespace.entries.len(),
+        };
+        for entry_names in &mut names[namespace.start..end] {
+            entry_names.clone_from(&path);
+        }
+        assign_namespaces(&namespace.children, &path, names);
+    }
 }
 
-fn main() -> Result<(), Box<std::error::Error>> {
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+/// A column value, which is a number, a string or missing
+enum Value {
+    Number(usize),
+    String(String),
+    None,
+}
+
+impl Value {
+    fn plain(&self) -> String {
+        match self {
+            Value::Number(x) => x.to_string(),
+            Value::String(x) => x.clone(),
+            Value::None => String::new(),
+        }
+    }
+
+    fn json(&self) -> String {
+        match self {
+            Value::Number(x) => x.to_string(),
+            Value::String(x) => json_string(x),
+            Value::None => "null".to_string(),
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     let opt = Opt::from_args();
 
-    let wad = wad::load_wad_file(opt.input)?;
+    let pattern = opt.pattern.as_deref().unwrap_or("*");
+    let pattern =
+        EntryIdPattern::new(pattern).ok_or_else(|| format!("Invalid pattern: {:?}", pattern))?;
+
+    let options = if opt.recover {
+        ParseOptions::recover()
+    } else {
+        ParseOptions::strict()
+    };
+    let (wad, warnings) =
+        wad::load_wad_file_with_options(&opt.input, &options).map_err(|e| e.to_string())?;
+    for warning in warnings {
+        eprintln!("{}", warning);
+    }
+
+    let mut namespaces = vec![vec![]; wad.len()];
+    assign_namespaces(&wad.as_slice().namespaces(), &[], &mut namespaces);
+
+    let mut in_namespace_filter = vec![opt.namespace.is_none(); wad.len()];
+    if let Some(name) = &opt.namespace {
+        for namespace in wad.as_slice().all_namespaces(name) {
+            let end = namespace.entries_start() + namespace.entries.len();
+            for x in &mut in_namespace_filter[namespace.entries_start()..end] {
+                *x = true;
+            }
+        }
+    }
+
+    let mut headers = vec!["index", "length", "name"];
+    headers.extend(opt.columns.iter().map(|column| column.name()));
+
+    match opt.format {
+        Format::Table => {
+            // Header line describing the WAD, when it can be identified. The
+            // digest is only taken on request, and is meaningless if the
+            // data was rewritten by --recover.
+            let known = if opt.identify && !opt.recover {
+                wad::identify(&wad)
+            } else {
+                None
+            };
+            match (known, wad::game(&wad.as_slice())) {
+                (Some(known), _) => println!("# {} ({})", known, known.title.game()),
+                (None, Some(game)) => println!("# {}", game),
+                (None, None) => (),
+            }
+        }
+        Format::Csv => println!("{}", headers.join(",")),
+        Format::Json => println!("["),
+    }
+
+    let mut first = true;
+    for (i, entry) in wad.try_entry_iter().enumerate() {
+        if !pattern.matches(&wad.entry_id(i).unwrap()) {
+            continue;
+        }
+        if !in_namespace_filter[i] {
+            continue;
+        }
+
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+
+        let namespace = namespaces[i].last().map(|x| &x[..]);
+        let mut values = vec![
+            Value::Number(i),
+            Value::Number(entry.lump.len()),
+            Value::String(entry.escaped_name()),
+        ];
+        for column in &opt.columns {
+            values.push(match column {
+                Column::Offset => Value::Number(wad.lump_range(i)?.start),
+                Column::Raw => Value::String(hex(entry.id.as_bytes())),
+                Column::Namespace if namespaces[i].is_empty() => Value::None,
+                Column::Namespace => Value::String(namespaces[i].join("/")),
+                Column::Type => {
+                    Value::String(ContentType::detect(&entry.id, entry.lump, namespace).to_string())
+                }
+                Column::Hash => Value::String(format!("{:x}", md5::compute(entry.lump))),
+            });
+        }
+
+        match opt.format {
+            Format::Table => {
+                let values: Vec<_> = values.iter().map(Value::plain).collect();
+                println!("{}", values.join("\t"));
+            }
+            Format::Csv => {
+                let values: Vec<_> = values.iter().map(|x| csv_field(&x.plain())).collect();
+                println!("{}", values.join(","));
+            }
+            Format::Json => {
+                let fields: Vec<_> = headers
+                    .iter()
+                    .zip(&values)
+                    .map(|(header, value)| format!("{}:{}", json_string(header), value.json()))
+                    .collect();
+                if !first {
+                    println!(",");
+                }
+                print!("  {{{}}}", fields.join(","));
+            }
+        }
+        first = false;
+    }
 
-    for (i, entry) in wad.entry_iter().enumerate() {
-        println!("{}\t{}\t{}", i, entry.lump.len(), entry.display_name());
+    if opt.format == Format::Json {
+        if !first {
+            println!();
+        }
+        println!("]");
     }
 
     Ok(())
@@ -0,0 +1,66 @@
+extern crate wad;
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+use wad::{Kind, Manifest, WadInfo, MANIFEST_FILE_NAME};
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "wad-pack",
+    about = "Build a WAD file from a directory of lumps, as written by wad-extract"
+)]
+struct Opt {
+    /// Input directory. If it contains a manifest, the manifest decides the
+    /// entries and their order. Otherwise they are inferred from the
+    /// directory layout.
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+
+    /// Output WAD file
+    #[structopt(parse(from_os_str))]
+    output: PathBuf,
+
+    /// Ignore the manifest and infer the entries from the directory layout
+    #[structopt(long = "infer")]
+    infer: bool,
+
+    /// Take the entries and their order from a DeuTex wadinfo.txt instead of
+    /// the manifest
+    #[structopt(long = "wadinfo", parse(from_os_str))]
+    wadinfo: Option<PathBuf>,
+
+    /// Write an IWAD. By default, the kind in the manifest is used, or PWAD
+    /// when there is no manifest or a wadinfo.txt is used.
+    #[structopt(long = "iwad")]
+    iwad: bool,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let opt = Opt::from_args();
+
+    let manifest_path = opt.input.join(MANIFEST_FILE_NAME);
+    let manifest = if let Some(wadinfo_path) = &opt.wadinfo {
+        let text = std::fs::read_to_string(wadinfo_path)?;
+        let wadinfo =
+            WadInfo::parse(&text).map_err(|e| format!("{}: {}", wadinfo_path.display(), e))?;
+        wadinfo.to_manifest(Kind::PWad, &opt.input)?
+    } else if !opt.infer && manifest_path.exists() {
+        let text = std::fs::read_to_string(&manifest_path)?;
+        Manifest::parse(&text).map_err(|e| format!("{}: {}", manifest_path.display(), e))?
+    } else {
+        Manifest::from_directory(Kind::PWad, &opt.input)?
+    };
+
+    let mut builder = manifest.to_builder(&opt.input)?;
+    if opt.iwad {
+        builder.set_kind(Kind::IWad);
+    }
+
+    let mut output = std::io::BufWriter::new(std::fs::File::create(&opt.output)?);
+    builder.write_to(&mut output)?;
+    output.flush()?;
+
+    Ok(())
+}
@@ -4,7 +4,7 @@ use std::io::Write;
 use std::path::PathBuf;
 
 use structopt::StructOpt;
-use wad::{load_wad_file, EntryId};
+use wad::{load_wad_file, Query};
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -22,8 +22,20 @@ struct Opt {
     /// To locate a lump that comes after some given lump, use +, for example
     /// "e1m3+linedefs".
     ///
-    /// To locate a lums that appears within a section delimited by *_START
-    /// and *_END, use /. For example "f/step1".
+    /// To locate a lump that appears within a section delimited by *_START
+    /// and *_END, use /. For example "f/step1". This also finds sections
+    /// delimited by the doubled forms, such as FF_START and FF_END.
+    ///
+    /// To locate a lump belonging to a map, use :, for example
+    /// "e1m3:linedefs". Unlike +, this never finds a lump of the next map.
+    ///
+    /// To pick among lumps with the same name, use #, for example
+    /// "playpal#1" for the second PLAYPAL or "playpal#-1" for the last one.
+    /// On its own, # selects a lump by index, for example "#42".
+    ///
+    /// Bytes that are not printable ASCII are written as \xNN, as wad-ls
+    /// lists them, for example "e1m1\x80". A backslash is taken literally
+    /// unless it starts such an escape or \\, so "vile\1" works as is.
     ///
     /// Matching is case insensitive.
     query: String,
@@ -34,47 +46,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let wad = load_wad_file(opt.input)?;
 
-    let mut wad = wad.as_slice();
-    let mut last = 0;
-    for (index, op) in opt.query.match_indices(|c| c == '+' || c == '/') {
-        let part = &opt.query[last..index];
-        last = index + op.len();
-
-        wad = match op {
-            "+" => {
-                let id = EntryId::from_str(part)
-                    .ok_or_else(|| format!("Invalid lump ID: {:?}", part))?;
-                let index = wad
-                    .index_of(id)
-                    .ok_or_else(|| format!("Lump not found: {:?}", part))?;
-                wad.slice(index + 1..)
-            }
-            "/" => {
-                let start_id = EntryId::from_str(format!("{}_START", part))
-                    .ok_or_else(|| format!("Invalid lump ID: {:?}_START", part))?;
-                let start_index = wad
-                    .index_of(start_id)
-                    .ok_or_else(|| format!("Lump not found: {:?}_START", part))?;
-
-                let end_id = EntryId::from_str(format!("{}_END", part))
-                    .ok_or_else(|| format!("Invalid lump ID: {:?}_END", part))?;
-                let end_index = wad
-                    .index_of(end_id)
-                    .ok_or_else(|| format!("Lump not found: {:?}_END", part))?;
-
-                wad.slice(start_index + 1..end_index)
-            }
-            _ => unreachable!(),
-        };
-    }
-
-    let name = &opt.query[last..];
-    let id = EntryId::from_str(name).ok_or_else(|| format!("Invalid lump ID: {:?}", name))?;
-    let index = wad
-        .index_of(id)
-        .ok_or_else(|| format!("Lump not found: {:?}", name))?;
-
-    let lump = wad.entry(index)?.lump;
+    let query = Query::parse(&opt.query).map_err(|e| e.to_string())?;
+    let lump = wad
+        .as_slice()
+        .query(&query)
+        .map_err(|e| e.to_string())?
+        .lump;
     std::io::stdout().lock().write_all(lump)?;
 
     Ok(())
@@ -0,0 +1,167 @@
+use std::io::{self, Write};
+
+use crate::error::Error;
+use crate::wad::*;
+use crate::wad_builder::write_wad;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct CompactOptions {
+    /// Write byte-identical lumps only once, and let all their directory
+    /// entries point to the same data
+    pub deduplicate: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CompactReport {
+    /// Size of the WAD file before compaction, from the start of the header
+    /// to the end of the directory
+    pub original_size: usize,
+
+    pub compacted_size: usize,
+
+    /// Number of directory entries that share lump data with an earlier
+    /// entry in the compacted WAD
+    pub shared_entries: usize,
+}
+
+impl CompactReport {
+    /// Zero if the compacted WAD is not smaller. This can happen when
+    /// entries in the original WAD share lump data and `deduplicate` is
+    /// off.
+    pub fn bytes_saved(&self) -> usize {
+        self.original_size.saturating_sub(self.compacted_size)
+    }
+}
+
+impl Wad {
+    /// Rewrite the WAD file without any unused space between lumps. Entry
+    /// order, names and lump contents are preserved, and the lump data is
+    /// written in directory order.
+    ///
+    /// Fails with `io::ErrorKind::InvalidData` wrapping an `Error` if any
+    /// directory entry is invalid.
+    pub fn compact_to(&self, options: &CompactOptions, w: impl Write) -> io::Result<CompactReport> {
+        let lumps = (0..self.len())
+            .map(|i| {
+                let entry = self.entry(i)?;
+                Ok((entry.id, entry.lump))
+            })
+            .collect::<Result<Vec<_>, Error>>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let written = write_wad(self.kind(), lumps.into_iter(), options.deduplicate, w)?;
+
+        let slice = self.as_slice();
+        Ok(CompactReport {
+            original_size: slice.data().len() + slice.len() * DIRECTORY_ENTRY_BYTE_SIZE,
+            compacted_size: written.size,
+            shared_entries: written.shared_entries,
+        })
+    }
+
+    /// Like `compact_to`, but returns the result as a new `Wad`
+    pub fn compact(&self, options: &CompactOptions) -> io::Result<(Wad, CompactReport)> {
+        let mut buf = vec![];
+        let report = self.compact_to(options, &mut buf)?;
+        let wad = parse_wad(buf).expect("write_wad writes valid WAD files");
+        Ok((wad, report))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+    use byteorder::{ByteOrder, LittleEndian};
+
+    fn sample() -> Vec<u8> {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        builder.push(b"E1M1", vec![]);
+        builder.push(b"THINGS", vec![1; 100]);
+        builder.push(b"REJECT", vec![0; 50]);
+        builder.push(b"E1M2", vec![]);
+        builder.push(b"THINGS", vec![2; 100]);
+        builder.push(b"REJECT", vec![0; 50]);
+        builder.to_bytes().unwrap()
+    }
+
+    /// Point entry 4 at entry 1's data, leaving the original data unused
+    fn with_gap() -> Vec<u8> {
+        let mut data = sample();
+        let directory_offset = LittleEndian::read_i32(&data[8..12]) as usize;
+        let (from, to) = (directory_offset + 16, directory_offset + 4 * 16);
+        let start = data[from..from + 4].to_vec();
+        data[to..to + 4].copy_from_slice(&start);
+        data
+    }
+
+    fn lumps(wad: &Wad) -> Vec<(EntryId, Vec<u8>)> {
+        wad.entry_iter().map(|e| (e.id, e.lump.to_vec())).collect()
+    }
+
+    #[test]
+    fn compact_wad_is_unchanged() {
+        let data = sample();
+        let wad = parse_wad(data.clone()).unwrap();
+
+        let mut buf = vec![];
+        let report = wad
+            .compact_to(&CompactOptions::default(), &mut buf)
+            .unwrap();
+        assert_eq!(buf, data);
+        assert_eq!(report.bytes_saved(), 0);
+        assert_eq!(report.shared_entries, 0);
+    }
+
+    #[test]
+    fn removes_unused_space() {
+        let wad = parse_wad(with_gap()).unwrap();
+        assert!(wad
+            .validate()
+            .iter()
+            .any(|f| matches!(f.kind, FindingKind::UnusedGap { .. })));
+
+        // Without deduplication, the shared lump is written twice, filling
+        // the gap again
+        let (compacted, report) = wad.compact(&CompactOptions::default()).unwrap();
+        assert_eq!(lumps(&compacted), lumps(&wad));
+        assert_eq!(report.original_size, report.compacted_size);
+
+        let options = CompactOptions { deduplicate: true };
+        let (compacted, report) = wad.compact(&options).unwrap();
+        assert_eq!(lumps(&compacted), lumps(&wad));
+        assert!(compacted.validate().is_empty());
+        assert_eq!(report.bytes_saved(), 150);
+        assert_eq!(report.shared_entries, 2);
+    }
+
+    #[test]
+    fn deduplicates_identical_lumps() {
+        let wad = parse_wad(sample()).unwrap();
+        let options = CompactOptions { deduplicate: true };
+        let (compacted, report) = wad.compact(&options).unwrap();
+
+        assert_eq!(lumps(&compacted), lumps(&wad));
+        assert_eq!(report.bytes_saved(), 50);
+        assert_eq!(report.shared_entries, 1);
+        assert_eq!(
+            compacted.lump(2).unwrap().as_ptr(),
+            compacted.lump(5).unwrap().as_ptr()
+        );
+    }
+
+    #[test]
+    fn fails_on_invalid_entries() {
+        let mut data = sample();
+        let directory_offset = LittleEndian::read_i32(&data[8..12]) as usize;
+        LittleEndian::write_i32(&mut data[directory_offset + 20..directory_offset + 24], -1);
+
+        let err = parse_wad(data)
+            .unwrap()
+            .compact(&CompactOptions::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.get_ref().unwrap().is::<Error>());
+    }
+}
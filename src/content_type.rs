@@ -0,0 +1,202 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::entry_id::EntryId;
+use crate::map::*;
+
+/// The kind of data in a lump, as guessed from its name, its namespace and
+/// its first few bytes
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ContentType {
+    /// Zero-length lump, such as a map header or a namespace marker
+    Empty,
+
+    /// `PLAYPAL`
+    Palette,
+
+    /// `COLORMAP`
+    Colormap,
+
+    /// `ENDOOM` and its equivalents in other games
+    EndScreen,
+
+    /// `TEXTURE1` and `TEXTURE2`
+    TextureDefinitions,
+
+    /// `PNAMES`
+    PatchNames,
+
+    /// One of the lumps of a binary map, such as `THINGS`
+    MapData,
+
+    /// A flat in the `F_START`/`F_END` namespace
+    Flat,
+
+    /// A picture in the Doom patch format
+    Picture,
+
+    /// A sound effect in the Doom sound format
+    DoomSound,
+
+    /// Music in the MUS format
+    Mus,
+
+    Midi,
+    Wave,
+    Png,
+
+    /// Printable ASCII text, such as `DEHACKED` or `TEXTMAP`
+    Text,
+
+    Unknown,
+}
+
+impl ContentType {
+    /// Guess the content type of a lump. `namespace` is the name of the
+    /// innermost namespace the lump is in, if any, as given by
+    /// `Namespace::name`.
+    pub fn detect(id: &EntryId, lump: &[u8], namespace: Option<&str>) -> ContentType {
+        if lump.is_empty() {
+            return ContentType::Empty;
+        }
+
+        let id = id.normalized();
+        let name = id.as_bytes();
+        match &name[..] {
+            b"PLAYPAL\0" => return ContentType::Palette,
+            b"COLORMAP" => return ContentType::Colormap,
+            b"ENDOOM\0\0" | b"ENDTEXT\0" | b"ENDSTRF\0" => return ContentType::EndScreen,
+            b"TEXTURE1" | b"TEXTURE2" => return ContentType::TextureDefinitions,
+            b"PNAMES\0\0" => return ContentType::PatchNames,
+            _ => (),
+        }
+        if BINARY_MAP_LUMPS.iter().any(|map_lump| is(id, map_lump)) {
+            return ContentType::MapData;
+        }
+
+        if lump.starts_with(b"MUS\x1a") {
+            ContentType::Mus
+        } else if lump.starts_with(b"MThd") {
+            ContentType::Midi
+        } else if lump.starts_with(b"RIFF") && lump.get(8..12) == Some(&b"WAVE"[..]) {
+            ContentType::Wave
+        } else if lump.starts_with(b"\x89PNG\r\n\x1a\n") {
+            ContentType::Png
+        } else if matches!(namespace, Some(x) if x.starts_with('F')) {
+            ContentType::Flat
+        } else if is_doom_sound(lump) {
+            ContentType::DoomSound
+        } else if is_picture(lump) {
+            ContentType::Picture
+        } else if is_text(lump) {
+            ContentType::Text
+        } else {
+            ContentType::Unknown
+        }
+    }
+}
+
+/// A format 3 header, followed by at least as many samples as it declares
+fn is_doom_sound(lump: &[u8]) -> bool {
+    lump.len() >= 8
+        && LittleEndian::read_u16(&lump[0..2]) == 3
+        && LittleEndian::read_u32(&lump[4..8]) as usize <= lump.len() - 8
+}
+
+/// A picture header with column offsets that all point into the lump, after
+/// the offsets themselves
+fn is_picture(lump: &[u8]) -> bool {
+    if lump.len() < 8 {
+        return false;
+    }
+    let width = LittleEndian::read_u16(&lump[0..2]) as usize;
+    let height = LittleEndian::read_u16(&lump[2..4]) as usize;
+    if width == 0 || height == 0 || width > 4096 || height > 4096 {
+        return false;
+    }
+
+    let columns_end = 8 + 4 * width;
+    match lump.get(8..columns_end) {
+        Some(offsets) => offsets.chunks(4).all(|offset| {
+            let offset = LittleEndian::read_u32(offset) as usize;
+            offset >= columns_end && offset < lump.len()
+        }),
+        None => false,
+    }
+}
+
+fn is_text(lump: &[u8]) -> bool {
+    lump.iter()
+        .all(|&x| x.is_ascii_graphic() || x.is_ascii_whitespace() || x == 0x1a)
+}
+
+impl std::fmt::Display for ContentType {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.write_str(match self {
+            ContentType::Empty => "empty",
+            ContentType::Palette => "palette",
+            ContentType::Colormap => "colormap",
+            ContentType::EndScreen => "end-screen",
+            ContentType::TextureDefinitions => "texture-definitions",
+            ContentType::PatchNames => "patch-names",
+            ContentType::MapData => "map-data",
+            ContentType::Flat => "flat",
+            ContentType::Picture => "picture",
+            ContentType::DoomSound => "doom-sound",
+            ContentType::Mus => "mus",
+            ContentType::Midi => "midi",
+            ContentType::Wave => "wave",
+            ContentType::Png => "png",
+            ContentType::Text => "text",
+            ContentType::Unknown => "unknown",
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn detect(name: &str, lump: &[u8]) -> ContentType {
+        ContentType::detect(&EntryId::from_str(name).unwrap(), lump, None)
+    }
+
+    #[test]
+    fn detects_by_name() {
+        assert_eq!(detect("PLAYPAL", &[0; 768]), ContentType::Palette);
+        assert_eq!(detect("playpal", &[0; 768]), ContentType::Palette);
+        assert_eq!(detect("THINGS", &[0; 10]), ContentType::MapData);
+        assert_eq!(detect("E1M1", &[]), ContentType::Empty);
+    }
+
+    #[test]
+    fn detects_by_contents() {
+        assert_eq!(detect("D_E1M1", b"MUS\x1a...."), ContentType::Mus);
+        assert_eq!(detect("DEHACKED", b"Patch File\r\n"), ContentType::Text);
+        assert_eq!(
+            detect("DSPISTOL", &[3, 0, 0x11, 0x2b, 2, 0, 0, 0, 0x80, 0x80]),
+            ContentType::DoomSound
+        );
+        assert_eq!(
+            detect("DSPISTOL", &[3, 0, 0x11, 0x2b, 9, 0, 0, 0]),
+            ContentType::Unknown
+        );
+
+        // 1x1 picture with one empty column
+        let picture = [1, 0, 1, 0, 0, 0, 0, 0, 12, 0, 0, 0, 0xff];
+        assert_eq!(detect("PATCH", &picture), ContentType::Picture);
+        assert_eq!(detect("PATCH", &picture[..12]), ContentType::Unknown);
+    }
+
+    #[test]
+    fn detects_flats_by_namespace() {
+        let id = EntryId::from_str("FLOOR0_1").unwrap();
+        assert_eq!(
+            ContentType::detect(&id, &[0; 4096], Some("F")),
+            ContentType::Flat
+        );
+        assert_eq!(
+            ContentType::detect(&id, &[0; 4096], None),
+            ContentType::Unknown
+        );
+    }
+}
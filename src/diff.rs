@@ -0,0 +1,580 @@
+use std::collections::HashMap;
+
+use crate::entry_id::EntryId;
+use crate::error::Error;
+use crate::map::MapRef;
+use crate::wad_slice::WadSlice;
+
+/// A difference between two WADs, as found by `diff`. Indices refer to the
+/// directory of the old or new WAD respectively.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// The entry is only in the new WAD
+    Added { index: usize, id: EntryId },
+
+    /// The entry is only in the old WAD
+    Removed { index: usize, id: EntryId },
+
+    /// The entry has the same lump data, but a different name
+    Renamed {
+        old_index: usize,
+        new_index: usize,
+        old_id: EntryId,
+        new_id: EntryId,
+    },
+
+    /// The entry has moved relative to the other entries
+    Moved {
+        old_index: usize,
+        new_index: usize,
+        id: EntryId,
+    },
+
+    /// The entry has the same name, but different lump data
+    Modified {
+        old_index: usize,
+        new_index: usize,
+        id: EntryId,
+        old_length: usize,
+        new_length: usize,
+    },
+}
+
+impl Change {
+    /// Index of the entry in the new WAD, or in the old WAD for removed
+    /// entries
+    fn sort_index(&self) -> (usize, usize) {
+        match *self {
+            Change::Removed { index, .. } => (0, index),
+            Change::Added { index, .. } => (1, index),
+            Change::Renamed { new_index, .. }
+            | Change::Moved { new_index, .. }
+            | Change::Modified { new_index, .. } => (1, new_index),
+        }
+    }
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Change::Added { index, id } => write!(fmt, "Added {} at {}", id, index),
+            Change::Removed { index, id } => write!(fmt, "Removed {} at {}", id, index),
+            Change::Renamed {
+                old_index,
+                new_index,
+                old_id,
+                new_id,
+            } => write!(
+                fmt,
+                "Renamed {} at {} to {} at {}",
+                old_id, old_index, new_id, new_index
+            ),
+            Change::Moved {
+                old_index,
+                new_index,
+                id,
+            } => write!(fmt, "Moved {} from {} to {}", id, old_index, new_index),
+            Change::Modified {
+                old_index,
+                new_index,
+                id,
+                old_length,
+                new_length,
+            } => write!(
+                fmt,
+                "Modified {} at {} -> {}: {} -> {} bytes",
+                id, old_index, new_index, old_length, new_length
+            ),
+        }
+    }
+}
+
+/// The map a lump belongs to, by normalized name and which occurrence of
+/// that map it is
+type MapScope = Option<(EntryId, usize)>;
+
+/// Key for aligning entries: The normalized name, the map the entry belongs
+/// to, and which occurrence of that name the entry is within the map, or
+/// outside of maps. Scoping by map keeps map lumps aligned when maps are
+/// added or removed before them.
+fn keys(wad: &WadSlice) -> Vec<(EntryId, MapScope, usize)> {
+    let mut scopes: Vec<MapScope> = vec![None; wad.len()];
+    let mut map_occurrences = HashMap::new();
+    for map in wad.maps() {
+        let name = map.name.normalized();
+        let occurrence = map_occurrences.entry(name).or_insert(0);
+        *occurrence += 1;
+
+        let lumps = map.index + 1..map.index + 1 + map.lumps.len();
+        for scope in &mut scopes[lumps] {
+            *scope = Some((name, *occurrence - 1));
+        }
+    }
+
+    let mut occurrences = HashMap::new();
+    wad.id_iter()
+        .zip(scopes)
+        .map(|(id, scope)| {
+            let id = id.normalized();
+            let occurrence = occurrences.entry((id, scope)).or_insert(0);
+            *occurrence += 1;
+            (id, scope, *occurrence - 1)
+        })
+        .collect()
+}
+
+fn lumps<'a>(wad: &WadSlice<'a>) -> Result<Vec<&'a [u8]>, Error> {
+    (0..wad.len()).map(|i| wad.lump(i)).collect()
+}
+
+/// Positions in `seq` of a longest strictly increasing subsequence
+fn longest_increasing_subsequence(seq: &[usize]) -> Vec<usize> {
+    // tails[k] is the position of the smallest value ending an increasing
+    // subsequence of length k + 1
+    let mut tails: Vec<usize> = vec![];
+    let mut predecessor = vec![None; seq.len()];
+
+    for (i, &x) in seq.iter().enumerate() {
+        let k = tails.partition_point(|&t| seq[t] < x);
+        predecessor[i] = if k > 0 { Some(tails[k - 1]) } else { None };
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+
+    let mut result = vec![];
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        result.push(i);
+        next = predecessor[i];
+    }
+    result.reverse();
+    result
+}
+
+/// Compare two WADs entry by entry.
+///
+/// Entries are aligned by name, so the n-th entry with a given name in the
+/// old WAD corresponds to the n-th entry with that name in the new WAD.
+/// The lumps of a map are counted within the map, so the `THINGS` of
+/// `E1M2` corresponds to the `THINGS` of `E1M2` even if maps were added
+/// before it. A map whose header was renamed is paired with the new map if
+/// a lump at the same position in both has the same name and data, and then
+/// only the header is reported as renamed. Names are compared with
+/// `EntryId::matches`. Other unaligned entries with identical, non-empty
+/// lump data are reported as renamed if their names differ.
+/// Aligned entries that do not keep their relative order are reported as
+/// moved, choosing the smallest set of moves.
+///
+/// Fails if any directory entry in either WAD is invalid.
+pub fn diff(old: &WadSlice, new: &WadSlice) -> Result<Vec<Change>, Error> {
+    let old_lumps = lumps(old)?;
+    let new_lumps = lumps(new)?;
+
+    let new_by_key: HashMap<_, _> = keys(new)
+        .into_iter()
+        .enumerate()
+        .map(|(index, key)| (key, index))
+        .collect();
+
+    let mut changes = vec![];
+
+    // Pairs of (old_index, new_index) for entries present in both
+    let mut pairs = vec![];
+    let mut old_matched = vec![false; old.len()];
+    let mut new_matched = vec![false; new.len()];
+
+    for (old_index, key) in keys(old).into_iter().enumerate() {
+        if let Some(&new_index) = new_by_key.get(&key) {
+            pairs.push((old_index, new_index));
+            old_matched[old_index] = true;
+            new_matched[new_index] = true;
+        }
+    }
+
+    // The lumps of a map with a renamed header are scoped by different
+    // names, so none of them are aligned yet. Pair such maps if a lump at
+    // the same position has the same name and data, and align their lumps
+    // by position.
+    let mut new_maps: Vec<_> = new
+        .maps()
+        .into_iter()
+        .filter(|map| !new_matched[map.index])
+        .collect();
+    let old_maps: Vec<_> = old
+        .maps()
+        .into_iter()
+        .filter(|map| !old_matched[map.index])
+        .collect();
+    for old_map in old_maps {
+        let common_lumps = |new_map: &MapRef| {
+            (0..old_map.lumps.len().min(new_map.lumps.len()))
+                .map(|i| (old_map.index + 1 + i, new_map.index + 1 + i))
+                .filter(|&(old_index, new_index)| {
+                    !old_matched[old_index]
+                        && !new_matched[new_index]
+                        && old
+                            .entry_id(old_index)
+                            .unwrap()
+                            .matches(&new.entry_id(new_index).unwrap())
+                })
+                .collect::<Vec<_>>()
+        };
+        let same_map = new_maps.iter().position(|new_map| {
+            common_lumps(new_map)
+                .into_iter()
+                .any(|(old_index, new_index)| {
+                    !old_lumps[old_index].is_empty() && old_lumps[old_index] == new_lumps[new_index]
+                })
+        });
+        let new_map = match same_map {
+            Some(position) => new_maps.remove(position),
+            None => continue,
+        };
+
+        let mut map_pairs = common_lumps(&new_map);
+        map_pairs.push((old_map.index, new_map.index));
+        for (old_index, new_index) in map_pairs {
+            pairs.push((old_index, new_index));
+            old_matched[old_index] = true;
+            new_matched[new_index] = true;
+        }
+        if !old_map.name.matches(&new_map.name) {
+            changes.push(Change::Renamed {
+                old_index: old_map.index,
+                new_index: new_map.index,
+                old_id: old_map.name,
+                new_id: new_map.name,
+            });
+        }
+    }
+
+    let mut new_unmatched_by_lump: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for (new_index, _) in new_matched.iter().enumerate().filter(|(_, &m)| !m) {
+        new_unmatched_by_lump
+            .entry(new_lumps[new_index])
+            .or_default()
+            .push(new_index);
+    }
+    for candidates in new_unmatched_by_lump.values_mut() {
+        candidates.reverse();
+    }
+
+    for (old_index, _) in old_matched.iter().enumerate().filter(|(_, &m)| !m) {
+        let lump = old_lumps[old_index];
+        let renamed = if lump.is_empty() {
+            None
+        } else {
+            new_unmatched_by_lump
+                .get_mut(lump)
+                .and_then(|candidates| candidates.pop())
+        };
+
+        match renamed {
+            Some(new_index) => {
+                pairs.push((old_index, new_index));
+                new_matched[new_index] = true;
+
+                let old_id = old.entry_id(old_index).unwrap();
+                let new_id = new.entry_id(new_index).unwrap();
+                if !old_id.matches(&new_id) {
+                    changes.push(Change::Renamed {
+                        old_index,
+                        new_index,
+                        old_id,
+                        new_id,
+                    });
+                }
+            }
+            None => changes.push(Change::Removed {
+                index: old_index,
+                id: old.entry_id(old_index).unwrap(),
+            }),
+        }
+    }
+
+    for (new_index, _) in new_matched.iter().enumerate().filter(|(_, &m)| !m) {
+        changes.push(Change::Added {
+            index: new_index,
+            id: new.entry_id(new_index).unwrap(),
+        });
+    }
+
+    for &(old_index, new_index) in &pairs {
+        let (old_lump, new_lump) = (old_lumps[old_index], new_lumps[new_index]);
+        if old_lump != new_lump {
+            changes.push(Change::Modified {
+                old_index,
+                new_index,
+                id: new.entry_id(new_index).unwrap(),
+                old_length: old_lump.len(),
+                new_length: new_lump.len(),
+            });
+        }
+    }
+
+    // The entries that keep their relative order are the longest
+    // increasing subsequence of new indices, in old order
+    pairs.sort_unstable();
+    let new_indices: Vec<_> = pairs.iter().map(|&(_, new_index)| new_index).collect();
+    let mut in_order = vec![false; pairs.len()];
+    for i in longest_increasing_subsequence(&new_indices) {
+        in_order[i] = true;
+    }
+    for (&(old_index, new_index), _) in pairs.iter().zip(in_order).filter(|(_, x)| !x) {
+        changes.push(Change::Moved {
+            old_index,
+            new_index,
+            id: new.entry_id(new_index).unwrap(),
+        });
+    }
+
+    changes.sort_by_key(Change::sort_index);
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    fn wad(lumps: &[(&str, &str)]) -> Wad {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        for (name, lump) in lumps {
+            builder.push(EntryId::from_str(name).unwrap(), lump.as_bytes());
+        }
+        parse_wad(builder.to_bytes().unwrap()).unwrap()
+    }
+
+    fn id(name: &str) -> EntryId {
+        EntryId::from_str(name).unwrap()
+    }
+
+    fn changes(old: &Wad, new: &Wad) -> Vec<Change> {
+        diff(&old.as_slice(), &new.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn identical_wads_have_no_changes() {
+        let a = wad(&[("A", "a"), ("B", "b")]);
+        assert_eq!(changes(&a, &a), []);
+    }
+
+    #[test]
+    fn added_removed_and_modified() {
+        let old = wad(&[("A", "a"), ("B", "b"), ("C", "c")]);
+        let new = wad(&[("A", "a2"), ("C", "c"), ("D", "d")]);
+        assert_eq!(
+            changes(&old, &new),
+            [
+                Change::Removed {
+                    index: 1,
+                    id: id("B")
+                },
+                Change::Modified {
+                    old_index: 0,
+                    new_index: 0,
+                    id: id("A"),
+                    old_length: 1,
+                    new_length: 2
+                },
+                Change::Added {
+                    index: 2,
+                    id: id("D")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_names_align_by_occurrence() {
+        let old = wad(&[
+            ("E1M1", ""),
+            ("THINGS", "t1"),
+            ("E1M2", ""),
+            ("THINGS", "t2"),
+        ]);
+        let new = wad(&[
+            ("E1M1", ""),
+            ("THINGS", "t1"),
+            ("E1M2", ""),
+            ("THINGS", "t2b"),
+        ]);
+        assert_eq!(
+            changes(&old, &new),
+            [Change::Modified {
+                old_index: 3,
+                new_index: 3,
+                id: id("THINGS"),
+                old_length: 2,
+                new_length: 3
+            }]
+        );
+    }
+
+    #[test]
+    fn inserted_map_keeps_later_maps_aligned() {
+        let old = wad(&[
+            ("E1M1", ""),
+            ("THINGS", "t1"),
+            ("LINEDEFS", "l1"),
+            ("E1M2", ""),
+            ("THINGS", "t2"),
+            ("LINEDEFS", "l2"),
+        ]);
+        let new = wad(&[
+            ("E1M1", ""),
+            ("THINGS", "t1"),
+            ("LINEDEFS", "l1"),
+            ("E1M9", ""),
+            ("THINGS", "t9"),
+            ("LINEDEFS", "l9"),
+            ("E1M2", ""),
+            ("THINGS", "t2"),
+            ("LINEDEFS", "l2"),
+        ]);
+        assert_eq!(
+            changes(&old, &new),
+            [
+                Change::Added {
+                    index: 3,
+                    id: id("E1M9")
+                },
+                Change::Added {
+                    index: 4,
+                    id: id("THINGS")
+                },
+                Change::Added {
+                    index: 5,
+                    id: id("LINEDEFS")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn renamed_map_aligns_lumps_by_position() {
+        let old = wad(&[
+            ("E1M1", ""),
+            ("THINGS", "t1"),
+            ("E1M2", ""),
+            ("THINGS", "t2"),
+            ("LINEDEFS", "l2"),
+        ]);
+        let new = wad(&[
+            ("E1M1", ""),
+            ("THINGS", "t1"),
+            ("E1M3", ""),
+            ("THINGS", "t2"),
+            ("LINEDEFS", "l3"),
+        ]);
+        assert_eq!(
+            changes(&old, &new),
+            [
+                Change::Renamed {
+                    old_index: 2,
+                    new_index: 2,
+                    old_id: id("E1M2"),
+                    new_id: id("E1M3")
+                },
+                Change::Modified {
+                    old_index: 4,
+                    new_index: 4,
+                    id: id("LINEDEFS"),
+                    old_length: 2,
+                    new_length: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn same_name_is_not_a_rename() {
+        let old = wad(&[("E1M1", ""), ("THINGS", "t"), ("LINEDEFS", "l")]);
+        let new = wad(&[
+            ("E1M5", ""),
+            ("THINGS", "t5"),
+            ("SIDEDEFS", "s"),
+            ("LINEDEFS", "l"),
+        ]);
+        assert_eq!(
+            changes(&old, &new),
+            [
+                Change::Removed {
+                    index: 0,
+                    id: id("E1M1")
+                },
+                Change::Removed {
+                    index: 1,
+                    id: id("THINGS")
+                },
+                Change::Added {
+                    index: 0,
+                    id: id("E1M5")
+                },
+                Change::Added {
+                    index: 1,
+                    id: id("THINGS")
+                },
+                Change::Added {
+                    index: 2,
+                    id: id("SIDEDEFS")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn renamed_by_content() {
+        let old = wad(&[("A", "a"), ("OLDNAME", "data"), ("C", "")]);
+        let new = wad(&[("A", "a"), ("NEWNAME", "data"), ("D", "")]);
+        assert_eq!(
+            changes(&old, &new),
+            [
+                Change::Removed {
+                    index: 2,
+                    id: id("C")
+                },
+                Change::Renamed {
+                    old_index: 1,
+                    new_index: 1,
+                    old_id: id("OLDNAME"),
+                    new_id: id("NEWNAME")
+                },
+                Change::Added {
+                    index: 2,
+                    id: id("D")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reordered_entries_are_moved() {
+        let old = wad(&[("A", "a"), ("B", "b"), ("C", "c"), ("D", "d")]);
+        let new = wad(&[("A", "a"), ("C", "c"), ("D", "d"), ("B", "b")]);
+        assert_eq!(
+            changes(&old, &new),
+            [Change::Moved {
+                old_index: 1,
+                new_index: 3,
+                id: id("B")
+            }]
+        );
+    }
+
+    #[test]
+    fn insertion_is_not_a_move() {
+        let old = wad(&[("A", "a"), ("B", "b")]);
+        let new = wad(&[("X", "x"), ("A", "a"), ("B", "b")]);
+        assert_eq!(
+            changes(&old, &new),
+            [Change::Added {
+                index: 0,
+                id: id("X")
+            }]
+        );
+    }
+}
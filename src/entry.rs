@@ -11,4 +11,9 @@ impl<'a> Entry<'a> {
     pub fn display_name(&self) -> &str {
         self.id.display()
     }
+
+    /// Lossless display representation. See `EntryId::escaped`.
+    pub fn escaped_name(&self) -> String {
+        self.id.escaped()
+    }
 }
@@ -1,3 +1,5 @@
+use std::convert::TryFrom;
+
 use byteorder::{ByteOrder, NativeEndian};
 
 #[derive(PartialEq, Eq, Hash, Copy, Clone)]
@@ -9,7 +11,11 @@ impl EntryId {
     }
 
     /// None if s is too long or if s contains non-ASCII characters.
-    /// Normalized to uppercase and zero-padded.
+    /// Normalized to uppercase and zero-padded. Any ASCII is taken
+    /// literally, including backslashes, as in `VILE\1`. Use `str::parse`
+    /// or `EntryId::try_from` to read names written by `escaped`, with an
+    /// `EntryIdError` saying what is wrong with an invalid name.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: impl AsRef<str>) -> Option<EntryId> {
         let buf = s.as_ref().as_bytes();
 
@@ -29,7 +35,31 @@ impl EntryId {
         Some(Self::from_bytes(&padded))
     }
 
-    pub fn as_bytes<'a>(&'a self) -> &'a [u8; 8] {
+    fn to_ascii_uppercase(self) -> EntryId {
+        let mut buf = *self.as_bytes();
+        buf.make_ascii_uppercase();
+        EntryId::from_bytes(&buf)
+    }
+
+    /// The form used for matching lump names: Uppercased, and with any
+    /// bytes after the first NUL set to zero. Some tools leave garbage
+    /// after the NUL terminator, and the engine ignores case.
+    pub fn normalized(&self) -> EntryId {
+        let mut buf = [0u8; 8];
+        let name = self.as_bytes().iter().take_while(|&&x| x != 0);
+        for (dst, src) in buf.iter_mut().zip(name) {
+            *dst = src.to_ascii_uppercase();
+        }
+        EntryId::from_bytes(&buf)
+    }
+
+    /// Case-insensitive comparison that ignores anything after the first
+    /// NUL. The derived `PartialEq` compares the raw bytes exactly.
+    pub fn matches(&self, other: &EntryId) -> bool {
+        self.normalized() == other.normalized()
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 8] {
         unsafe {
             // I believe this is safe because the target type does not
             // have any particular alignment requirements
@@ -37,14 +67,14 @@ impl EntryId {
             // Also, due to use of NativeEndian, this should work equally
             // well regardless of endianness.
 
-            std::mem::transmute(&self.0)
+            std::mem::transmute::<&u64, &[u8; 8]>(&self.0)
         }
     }
 
     /// Lossy display representation. If this was created with from_bytes
     /// with a buffer containing non-ASCII characters, this function will
-    /// return "?".
-    pub fn display<'a>(&'a self) -> &'a str {
+    /// return "?". See `escaped` for a lossless alternative.
+    pub fn display(&self) -> &str {
         let buf = self.as_bytes();
 
         let is_ascii = buf.iter().all(u8::is_ascii);
@@ -59,6 +89,131 @@ impl EntryId {
 
         std::str::from_utf8(name).unwrap()
     }
+
+    /// Lossless display representation. Printable ASCII is written as is,
+    /// except for backslash, which is written as `\\`. Other bytes are
+    /// written as `\xNN`, so `E1M1` followed by byte 0x80 is `E1M1\x80`.
+    /// Trailing NULs are left out, except for a name of only NULs, which is
+    /// `\x00`. `from_escaped` parses this back.
+    pub fn escaped(&self) -> String {
+        let bytes = self.as_bytes();
+        let len = bytes.iter().rposition(|&x| x != 0).map_or(1, |x| x + 1);
+
+        let mut escaped = String::new();
+        for &x in &bytes[..len] {
+            match x {
+                b'\\' => escaped.push_str("\\\\"),
+                0x21..=0x7e => escaped.push(x as char),
+                _ => escaped.push_str(&format!("\\x{:02X}", x)),
+            }
+        }
+        escaped
+    }
+
+    /// Parse the representation given by `escaped`. Unlike `from_str` and
+    /// `str::parse`, the name is not uppercased, so that any entry ID
+    /// survives a round trip.
+    pub fn from_escaped(s: &str) -> Result<EntryId, EntryIdError> {
+        let mut buf = [0u8; 8];
+        let mut len = 0;
+
+        let mut rest = s;
+        while let Some(c) = rest.chars().next() {
+            let (x, escape_len) = match c {
+                '\\' => parse_escape(rest)?,
+                '!'..='~' => (c as u8, 1),
+                _ => return Err(EntryIdError::InvalidCharacter(c)),
+            };
+            rest = &rest[escape_len..];
+
+            *buf.get_mut(len).ok_or(EntryIdError::TooLong)? = x;
+            len += 1;
+        }
+
+        if len == 0 {
+            return Err(EntryIdError::Empty);
+        }
+
+        Ok(EntryId::from_bytes(&buf))
+    }
+}
+
+/// The byte given by the escape sequence at the start of `s`, and the length
+/// of the sequence
+fn parse_escape(s: &str) -> Result<(u8, usize), EntryIdError> {
+    let invalid = |len| EntryIdError::InvalidEscape(s[..len].to_string());
+
+    let mut chars = s.chars().skip(1);
+    match chars.next() {
+        Some('\\') => Ok((b'\\', 2)),
+        Some('x') => {
+            let mut value = 0;
+            let mut len = 2;
+            for _ in 0..2 {
+                let c = chars.next().ok_or_else(|| invalid(len))?;
+                len += c.len_utf8();
+                value = value * 16 + c.to_digit(16).ok_or_else(|| invalid(len))? as u8;
+            }
+            Ok((value, len))
+        }
+        Some(c) => Err(invalid(1 + c.len_utf8())),
+        None => Err(invalid(1)),
+    }
+}
+
+/// The reason a string is not a valid escaped entry ID. See
+/// `EntryId::from_escaped`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryIdError {
+    Empty,
+
+    /// The name is longer than 8 bytes, after resolving escapes
+    TooLong,
+
+    /// A character other than printable ASCII. Such bytes must be escaped.
+    InvalidCharacter(char),
+
+    /// A backslash that is not followed by another backslash or by `x` and
+    /// two hex digits
+    InvalidEscape(String),
+}
+
+impl std::fmt::Display for EntryIdError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EntryIdError::Empty => write!(fmt, "Lump name is empty"),
+            EntryIdError::TooLong => write!(fmt, "Lump name is longer than 8 bytes"),
+            EntryIdError::InvalidCharacter(c) => write!(
+                fmt,
+                "Invalid character in lump name: {:?}. Use \\xNN for bytes other than printable ASCII",
+                c
+            ),
+            EntryIdError::InvalidEscape(x) => {
+                write!(fmt, "Invalid escape sequence in lump name: {:?}", x)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EntryIdError {}
+
+/// Parses a lump name like `EntryId::from_escaped`, and uppercases it. Unlike
+/// the inherent `EntryId::from_str`, backslashes must start an escape.
+impl std::str::FromStr for EntryId {
+    type Err = EntryIdError;
+
+    fn from_str(s: &str) -> Result<EntryId, EntryIdError> {
+        EntryId::from_escaped(s).map(EntryId::to_ascii_uppercase)
+    }
+}
+
+/// The same as `str::parse`
+impl TryFrom<&str> for EntryId {
+    type Error = EntryIdError;
+
+    fn try_from(s: &str) -> Result<EntryId, EntryIdError> {
+        s.parse()
+    }
 }
 
 impl std::fmt::Debug for EntryId {
@@ -133,14 +288,14 @@ mod test {
     #[test]
     fn from_str() {
         let id = "E1M1";
-        let entry_id = EntryId::from_str(&id);
+        let entry_id = EntryId::from_str(id);
         assert!(entry_id.is_some());
     }
 
     #[test]
     fn from_str_gives_correct_as_bytes() {
         let id = "E1M1";
-        let entry_id = EntryId::from_str(&id).unwrap();
+        let entry_id = EntryId::from_str(id).unwrap();
         assert_eq!(entry_id.as_bytes(), b"E1M1\0\0\0\0");
     }
 
@@ -168,7 +323,7 @@ mod test {
     #[test]
     fn from_str_eq_to_string() {
         let id = "E1M1";
-        let entry_id = EntryId::from_str(&id).unwrap();
+        let entry_id = EntryId::from_str(id).unwrap();
         let string = entry_id.to_string();
         assert_eq!(id, string);
     }
@@ -194,6 +349,111 @@ mod test {
         );
     }
 
+    #[test]
+    fn normalized_is_uppercase() {
+        assert_eq!(
+            EntryId::from_bytes(b"e1m1\0\0\0\0").normalized(),
+            EntryId::from_str("E1M1").unwrap()
+        );
+    }
+
+    #[test]
+    fn normalized_ignores_garbage_after_nul() {
+        let id = EntryId::from_bytes(b"E1M1\0xyz");
+        assert_eq!(id.normalized().as_bytes(), b"E1M1\0\0\0\0");
+        assert_eq!(id.as_bytes(), b"E1M1\0xyz");
+    }
+
+    #[test]
+    fn matches_is_not_byte_exact() {
+        let a = EntryId::from_bytes(b"e1m1\0xyz");
+        let b = EntryId::from_str("E1M1").unwrap();
+        assert!(a.matches(&b));
+        assert!(a != b);
+        assert!(!a.matches(&EntryId::from_str("E1M").unwrap()));
+    }
+
+    #[test]
+    fn escaping_is_lossless() {
+        for name in &[
+            b"E1M1\x80\0\0\0",
+            b"VILE\\1\0\0",
+            b"a b\0junk",
+            b"\0\0\0\0\0\0\0X",
+            b"\0\0\0\0\0\0\0\0",
+        ] {
+            let id = EntryId::from_bytes(name);
+            assert_eq!(EntryId::from_escaped(&id.escaped()), Ok(id));
+        }
+        assert_eq!(
+            EntryId::from_bytes(b"E1M1\x80\0\0\0").escaped(),
+            "E1M1\\x80"
+        );
+        assert_eq!(EntryId::from_bytes(b"E1M1\0\0\0\0").escaped(), "E1M1");
+    }
+
+    #[test]
+    fn parses_escaped() {
+        assert_eq!(
+            EntryId::from_escaped("e1m1\\x8f"),
+            Ok(EntryId::from_bytes(b"e1m1\x8f\0\0\0"))
+        );
+        assert_eq!(
+            "e1m1\\x8f".parse(),
+            Ok(EntryId::from_bytes(b"E1M1\x8f\0\0\0"))
+        );
+        assert_eq!(
+            EntryId::try_from("a\\\\B"),
+            Ok(EntryId::from_bytes(b"A\\B\0\0\0\0\0"))
+        );
+    }
+
+    #[test]
+    fn from_str_takes_ascii_literally() {
+        assert_eq!("e1m1".parse(), Ok(EntryId::from_str("E1M1").unwrap()));
+        assert_eq!(
+            EntryId::from_str("vile\\1"),
+            Some(EntryId::from(b"VILE\\1"))
+        );
+        assert_eq!(EntryId::from_str("A B"), Some(EntryId::from(b"A B")));
+        assert_eq!(EntryId::from_str(""), Some(EntryId::from_bytes(&[0; 8])));
+        assert_eq!(EntryId::from_str("TOOLONGNA"), None);
+        assert_eq!(EntryId::from_str("Ä"), None);
+
+        assert!("VILE\\1".parse::<EntryId>().is_err());
+        assert_eq!(
+            "vile\\\\1".parse(),
+            Ok(EntryId::from_str("VILE\\1").unwrap())
+        );
+    }
+
+    #[test]
+    fn escaped_parse_errors() {
+        let parse = EntryId::from_escaped;
+        assert_eq!(parse(""), Err(EntryIdError::Empty));
+        assert_eq!(parse("TOOLONGNA"), Err(EntryIdError::TooLong));
+        assert_eq!(
+            parse("\\x00\\x00\\x00\\x00\\x00\\x00\\x00\\x00\\x00"),
+            Err(EntryIdError::TooLong)
+        );
+        assert_eq!(parse("A B"), Err(EntryIdError::InvalidCharacter(' ')));
+        assert_eq!(parse("Ä"), Err(EntryIdError::InvalidCharacter('Ä')));
+        assert_eq!(parse("\\q"), Err(EntryIdError::InvalidEscape("\\q".into())));
+        assert_eq!(
+            parse("A\\x8"),
+            Err(EntryIdError::InvalidEscape("\\x8".into()))
+        );
+        assert_eq!(
+            parse("\\x+1"),
+            Err(EntryIdError::InvalidEscape("\\x+".into()))
+        );
+        assert_eq!(
+            parse("\\xÄÄ"),
+            Err(EntryIdError::InvalidEscape("\\xÄ".into()))
+        );
+        assert_eq!(parse("\\"), Err(EntryIdError::InvalidEscape("\\".into())));
+    }
+
     #[test]
     fn from_array8_impl() {
         assert_eq!(EntryId::from_bytes(b"E1M1\0\0\0\0"), b"E1M1\0\0\0\0".into());
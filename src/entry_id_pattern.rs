@@ -0,0 +1,136 @@
+use crate::entry_id::EntryId;
+
+/// A DOS-style wildcard pattern for lump names. `*` matches any number of
+/// characters and `?` matches exactly one. Matching is case insensitive, and
+/// ignores anything after the first NUL in the name, like
+/// `EntryId::matches`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryIdPattern {
+    pattern: Vec<u8>,
+}
+
+impl EntryIdPattern {
+    /// None if the pattern contains non-ASCII characters, or if it requires
+    /// more than 8 characters and so can never match a lump name.
+    pub fn new(pattern: impl AsRef<str>) -> Option<EntryIdPattern> {
+        let pattern = pattern.as_ref().as_bytes();
+
+        if !pattern.is_ascii() {
+            return None;
+        }
+
+        let required_length = pattern.iter().filter(|&&x| x != b'*').count();
+        if required_length > 8 {
+            return None;
+        }
+
+        // Runs of * match the same as a single *
+        let mut collapsed = pattern.to_ascii_uppercase();
+        collapsed.dedup_by(|a, b| *a == b'*' && *b == b'*');
+
+        Some(EntryIdPattern { pattern: collapsed })
+    }
+
+    pub fn matches(&self, id: &EntryId) -> bool {
+        let id = id.normalized();
+        let name = id.as_bytes();
+        let name = &name[..name.iter().position(|&x| x == 0).unwrap_or(8)];
+        glob_match(&self.pattern, name)
+    }
+}
+
+/// Iterative matching that backtracks only to the most recent `*`, so it
+/// runs in time proportional to the product of the lengths
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Position of the last * in the pattern, and the name position it was
+    // tried at
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&x) if x == b'?' || x == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // Let the last * consume one more character
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&x| x == b'*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        EntryIdPattern::new(pattern)
+            .unwrap()
+            .matches(&EntryId::from_str(name).unwrap())
+    }
+
+    #[test]
+    fn literal() {
+        assert!(matches("E1M1", "E1M1"));
+        assert!(!matches("E1M1", "E1M10"));
+        assert!(!matches("E1M1", "E1M"));
+    }
+
+    #[test]
+    fn star() {
+        assert!(matches("DS*", "DSPISTOL"));
+        assert!(matches("DS*", "DS"));
+        assert!(!matches("DS*", "DPPISTOL"));
+        assert!(matches("*", "PLAYPAL"));
+        assert!(matches("*_START", "FF_START"));
+        assert!(matches("*_START", "_START"));
+        assert!(!matches("*_START", "F_END"));
+    }
+
+    #[test]
+    fn long_runs_of_stars() {
+        let pattern = format!("{}X", "*".repeat(40));
+        assert!(!matches(&pattern, "ABCDEFGH"));
+        assert!(matches(&pattern, "ABCDEFGX"));
+        assert!(matches("*A*B*", "XAXXBX"));
+        assert!(!matches("*A*B*", "XBXXAX"));
+        assert_eq!(EntryIdPattern::new("A***B**").unwrap().pattern, b"A*B*");
+    }
+
+    #[test]
+    fn question_mark() {
+        assert!(matches("E?M?", "E1M1"));
+        assert!(!matches("E?M?", "E1M10"));
+        assert!(matches("TROO??", "TROOA1"));
+        assert!(!matches("TROO??", "TROOA"));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        assert!(matches("e?m?", "E1M1"));
+        assert!(EntryIdPattern::new("E1M1")
+            .unwrap()
+            .matches(&EntryId::from_bytes(b"e1m1\0xyz")));
+    }
+
+    #[test]
+    fn rejects_impossible_patterns() {
+        assert!(EntryIdPattern::new("TOOLONGNA").is_none());
+        assert!(EntryIdPattern::new("TOOLONG*?").is_some());
+        assert!(EntryIdPattern::new("TOOLONG??").is_none());
+        assert!(EntryIdPattern::new("Æ*").is_none());
+    }
+}
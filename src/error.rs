@@ -1,3 +1,7 @@
+use std::path::{Path, PathBuf};
+
+use crate::entry_id::EntryId;
+
 macro_rules! verify {
     ($condition:expr, $err:expr) => {
         if !$condition {
@@ -8,44 +12,196 @@ macro_rules! verify {
 
 #[derive(Debug)]
 pub enum Error {
-    Invalid,
-    InvalidLength,
-    InvalidHeader,
-    InvalidEntry,
-    OutOfBounds,
+    /// The entry count or directory offset in the header is negative, or
+    /// they describe a directory that does not fit in the address space
+    Invalid {
+        n_entries: i32,
+        directory_offset: i32,
+    },
+
+    /// The file is too short to contain the header and directory
+    InvalidLength {
+        expected: usize,
+        actual: usize,
+    },
+
+    /// The file does not start with `IWAD` or `PWAD`
+    InvalidHeader {
+        magic: [u8; 4],
+    },
+
+    /// The start and length of a directory entry do not describe a lump
+    /// within the file
+    InvalidEntry {
+        /// The index of the entry in the directory, if known. For errors
+        /// from a `WadSlice`, this is relative to the start of the slice.
+        index: Option<usize>,
+        id: EntryId,
+        start: i32,
+        length: i32,
+
+        /// Lumps must end at or before this offset, where the directory
+        /// starts
+        directory_offset: usize,
+
+        /// The length of the whole file
+        file_length: usize,
+    },
+
+    OutOfBounds {
+        index: usize,
+        len: usize,
+    },
+}
+
+impl Error {
+    /// Fill in the directory index for errors about a specific entry
+    pub(crate) fn at_index(self, index: usize) -> Error {
+        match self {
+            Error::InvalidEntry {
+                id,
+                start,
+                length,
+                directory_offset,
+                file_length,
+                ..
+            } => Error::InvalidEntry {
+                index: Some(index),
+                id,
+                start,
+                length,
+                directory_offset,
+                file_length,
+            },
+            e => e,
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(
-            fmt,
-            "{}",
-            match self {
-                Error::Invalid => "Invalid WAD file",
-                Error::InvalidLength => "Invalid WAD file length",
-                Error::InvalidHeader => "Invalid WAD file header",
-                Error::InvalidEntry => "Invalid WAD file entry",
-                Error::OutOfBounds => "Index out of bounds",
+        match self {
+            Error::Invalid {
+                n_entries,
+                directory_offset,
+            } => write!(
+                fmt,
+                "Invalid WAD file: {} entries at directory offset {}",
+                n_entries, directory_offset
+            ),
+            Error::InvalidLength { expected, actual } => write!(
+                fmt,
+                "Invalid WAD file length: {} bytes, expected at least {}",
+                actual, expected
+            ),
+            Error::InvalidHeader { magic } => write!(
+                fmt,
+                "Invalid WAD file header: {:?}",
+                String::from_utf8_lossy(magic)
+            ),
+            Error::InvalidEntry {
+                index,
+                id,
+                start,
+                length,
+                directory_offset,
+                file_length,
+            } => {
+                write!(fmt, "Invalid WAD file entry")?;
+                if let Some(index) = index {
+                    write!(fmt, " {}", index)?;
+                }
+                write!(
+                    fmt,
+                    " ({}): start {}, length {} is outside of the lump data, which ends \
+                     at the directory at offset {} in the file of {} bytes",
+                    id.escaped(),
+                    start,
+                    length,
+                    directory_offset,
+                    file_length
+                )
             }
-        )
+            Error::OutOfBounds { index, len } => write!(
+                fmt,
+                "Index out of bounds: {} is not less than {}",
+                index, len
+            ),
+        }
     }
 }
 
 impl std::error::Error for Error {}
 
 #[derive(Debug)]
-pub enum LoadError {
+pub enum LoadErrorKind {
     Error(Error),
     IoError(std::io::Error),
 }
 
+#[derive(Debug)]
+pub struct LoadError {
+    path: Option<PathBuf>,
+    kind: LoadErrorKind,
+}
+
+impl LoadError {
+    /// The file that was being loaded, if known
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn kind(&self) -> &LoadErrorKind {
+        &self.kind
+    }
+
+    pub fn into_kind(self) -> LoadErrorKind {
+        self.kind
+    }
+
+    pub(crate) fn with_path(self, path: impl AsRef<Path>) -> LoadError {
+        LoadError {
+            path: Some(path.as_ref().to_owned()),
+            kind: self.kind,
+        }
+    }
+}
+
+impl From<Error> for LoadError {
+    fn from(error: Error) -> LoadError {
+        LoadError {
+            path: None,
+            kind: LoadErrorKind::Error(error),
+        }
+    }
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(error: std::io::Error) -> LoadError {
+        LoadError {
+            path: None,
+            kind: LoadErrorKind::IoError(error),
+        }
+    }
+}
+
 impl std::fmt::Display for LoadError {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            LoadError::Error(e) => write!(fmt, "{}", e),
-            LoadError::IoError(e) => write!(fmt, "{}", e),
+        if let Some(path) = &self.path {
+            write!(fmt, "{}: ", path.display())?;
+        }
+        match &self.kind {
+            LoadErrorKind::Error(e) => write!(fmt, "{}", e),
+            LoadErrorKind::IoError(e) => write!(fmt, "{}", e),
         }
     }
 }
 
-impl std::error::Error for LoadError {}
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            LoadErrorKind::Error(e) => Some(e),
+            LoadErrorKind::IoError(e) => Some(e),
+        }
+    }
+}
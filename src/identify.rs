@@ -0,0 +1,259 @@
+use crate::wad::*;
+use crate::wad_slice::WadSlice;
+
+/// A game using a variant of the Doom engine
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Game {
+    /// Doom, with maps named `ExMy`
+    Doom,
+
+    /// Doom II and Final Doom, with maps named `MAPxx`
+    Doom2,
+
+    Heretic,
+    Hexen,
+    Strife,
+}
+
+impl std::fmt::Display for Game {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.write_str(match self {
+            Game::Doom => "Doom",
+            Game::Doom2 => "Doom II",
+            Game::Heretic => "Heretic",
+            Game::Hexen => "Hexen",
+            Game::Strife => "Strife",
+        })
+    }
+}
+
+/// A commercial or free IWAD
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Title {
+    DoomShareware,
+    Doom,
+    UltimateDoom,
+    Doom2,
+    Tnt,
+    Plutonia,
+    Heretic,
+    Hexen,
+    Strife,
+    ChexQuest,
+    Freedoom1,
+    Freedoom2,
+}
+
+impl Title {
+    pub fn game(&self) -> Game {
+        match self {
+            Title::DoomShareware
+            | Title::Doom
+            | Title::UltimateDoom
+            | Title::ChexQuest
+            | Title::Freedoom1 => Game::Doom,
+            Title::Doom2 | Title::Tnt | Title::Plutonia | Title::Freedoom2 => Game::Doom2,
+            Title::Heretic => Game::Heretic,
+            Title::Hexen => Game::Hexen,
+            Title::Strife => Game::Strife,
+        }
+    }
+}
+
+impl std::fmt::Display for Title {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.write_str(match self {
+            Title::DoomShareware => "Doom Shareware",
+            Title::Doom => "Doom",
+            Title::UltimateDoom => "The Ultimate Doom",
+            Title::Doom2 => "Doom II: Hell on Earth",
+            Title::Tnt => "Final Doom: TNT: Evilution",
+            Title::Plutonia => "Final Doom: The Plutonia Experiment",
+            Title::Heretic => "Heretic",
+            Title::Hexen => "Hexen: Beyond Heretic",
+            Title::Strife => "Strife",
+            Title::ChexQuest => "Chex Quest",
+            Title::Freedoom1 => "Freedoom: Phase 1",
+            Title::Freedoom2 => "Freedoom: Phase 2",
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IdentifiedBy {
+    /// The MD5 digest of the file matches a known release
+    Digest,
+
+    /// The file has the lumps of the title, but is not a known release. It
+    /// may be a different version, or it may have been modified.
+    Lumps,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KnownWad {
+    pub title: Title,
+
+    /// The release version, when identified by digest
+    pub version: Option<&'static str>,
+
+    pub identified_by: IdentifiedBy,
+}
+
+impl std::fmt::Display for KnownWad {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.title)?;
+        if let Some(version) = self.version {
+            write!(fmt, " v{}", version)?;
+        }
+        Ok(())
+    }
+}
+
+const KNOWN_DIGESTS: &[(&str, Title, &str)] = &[
+    (
+        "f0cefca49926d00903cf57551d901abe",
+        Title::DoomShareware,
+        "1.9",
+    ),
+    ("1cd63c5ddff1bf8ce844237f580e9cf3", Title::Doom, "1.9"),
+    (
+        "c4fe9fd920207691a9f493668e0a2083",
+        Title::UltimateDoom,
+        "1.9",
+    ),
+    ("30e3c2d0350b67bfbf47271970b74b2f", Title::Doom2, "1.666"),
+    ("25e1459ca71d321525f84628f45ca8cd", Title::Doom2, "1.9"),
+    ("4e158d9953c79ccf97bd0663244cc6b6", Title::Tnt, "1.9"),
+    ("75c8cf89566741fa9d22447604053bd7", Title::Plutonia, "1.9"),
+    ("66d686b1ed6d35ff103f15dbd30e0341", Title::Heretic, "1.3"),
+    ("abb033caf81e26f12a2103e1fa25453f", Title::Hexen, "1.1"),
+    ("2fed2031a5b03892106e0f117f17901f", Title::Strife, "1.2"),
+    ("25485721882b050afa96a56e5758dd52", Title::ChexQuest, "1.0"),
+];
+
+fn has(wad: &WadSlice, name: &[u8; 8]) -> bool {
+    wad.index_of(name).is_some()
+}
+
+/// Detect which game a WAD is for from the lumps in it. Works for IWADs as
+/// well as PWADs that contain maps or game-specific lumps, but can't tell
+/// Doom II apart from Hexen or Strife for PWADs containing only maps.
+pub fn game(wad: &WadSlice) -> Option<Game> {
+    if has(wad, b"ENDSTRF\0") {
+        Some(Game::Strife)
+    } else if has(wad, b"ADVISOR\0") {
+        Some(Game::Heretic)
+    } else if has(wad, b"STARTUP\0") {
+        Some(Game::Hexen)
+    } else if has(wad, b"MAP01\0\0\0") {
+        Some(Game::Doom2)
+    } else if has(wad, b"E1M1\0\0\0\0") {
+        Some(Game::Doom)
+    } else {
+        None
+    }
+}
+
+/// Guess the title of an IWAD from its lumps
+fn title_from_lumps(wad: &WadSlice) -> Option<Title> {
+    if has(wad, b"FREEDOOM") {
+        return match game(wad)? {
+            Game::Doom => Some(Title::Freedoom1),
+            Game::Doom2 => Some(Title::Freedoom2),
+            _ => None,
+        };
+    }
+
+    match game(wad)? {
+        Game::Doom if has(wad, b"E4M1\0\0\0\0") => Some(Title::UltimateDoom),
+        Game::Doom if has(wad, b"E2M1\0\0\0\0") => Some(Title::Doom),
+        Game::Doom => Some(Title::DoomShareware),
+        Game::Doom2 => Some(Title::Doom2),
+        Game::Heretic => Some(Title::Heretic),
+        Game::Hexen => Some(Title::Hexen),
+        Game::Strife => Some(Title::Strife),
+    }
+}
+
+/// Identify a known IWAD. The MD5 digest of the file is compared against
+/// known releases. Failing that, IWADs are identified by their lumps.
+/// PWADs are only identified by digest.
+///
+/// The digest covers the file from the start of the header to the end of
+/// the directory, which is the entire file for all known releases.
+pub fn identify(wad: &Wad) -> Option<KnownWad> {
+    let digest = format!("{:x}", md5::compute(wad.file_data()));
+
+    if let Some(&(_, title, version)) = KNOWN_DIGESTS.iter().find(|(x, _, _)| *x == digest) {
+        return Some(KnownWad {
+            title,
+            version: Some(version),
+            identified_by: IdentifiedBy::Digest,
+        });
+    }
+
+    if wad.kind() != Kind::IWad {
+        return None;
+    }
+
+    Some(KnownWad {
+        title: title_from_lumps(&wad.as_slice())?,
+        version: None,
+        identified_by: IdentifiedBy::Lumps,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    fn wad(kind: Kind, names: &[&str]) -> Wad {
+        let mut builder = WadBuilder::new(kind);
+        for name in names {
+            builder.push(EntryId::from_str(name).unwrap(), vec![]);
+        }
+        parse_wad(builder.to_bytes().unwrap()).unwrap()
+    }
+
+    fn title(names: &[&str]) -> Option<Title> {
+        identify(&wad(Kind::IWad, names)).map(|known| known.title)
+    }
+
+    #[test]
+    fn detects_game() {
+        let game = |names| game(&wad(Kind::PWad, names).as_slice());
+        assert_eq!(game(&["E1M1", "THINGS"]), Some(Game::Doom));
+        assert_eq!(game(&["MAP01", "THINGS"]), Some(Game::Doom2));
+        assert_eq!(game(&["MAP01", "STARTUP"]), Some(Game::Hexen));
+        assert_eq!(game(&["E1M1", "ADVISOR"]), Some(Game::Heretic));
+        assert_eq!(game(&["MAP01", "ENDSTRF"]), Some(Game::Strife));
+        assert_eq!(game(&["PLAYPAL"]), None);
+    }
+
+    #[test]
+    fn identifies_iwads_by_lumps() {
+        assert_eq!(title(&["E1M1"]), Some(Title::DoomShareware));
+        assert_eq!(title(&["E1M1", "E2M1", "E4M1"]), Some(Title::UltimateDoom));
+        assert_eq!(title(&["MAP01"]), Some(Title::Doom2));
+        assert_eq!(title(&["FREEDOOM", "MAP01"]), Some(Title::Freedoom2));
+        assert_eq!(title(&["PLAYPAL"]), None);
+
+        let known = identify(&wad(Kind::IWad, &["E1M1", "E2M1"])).unwrap();
+        assert_eq!(known.identified_by, IdentifiedBy::Lumps);
+        assert_eq!(known.version, None);
+        assert_eq!(known.title.game(), Game::Doom);
+    }
+
+    #[test]
+    fn pwads_are_not_identified_by_lumps() {
+        assert_eq!(identify(&wad(Kind::PWad, &["MAP01"])), None);
+    }
+
+    #[test]
+    fn digest_covers_whole_file() {
+        let data = WadBuilder::new(Kind::PWad).to_bytes().unwrap();
+        let wad = parse_wad(data.clone()).unwrap();
+        assert_eq!(wad.file_data(), &data[..]);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::Entry;
 use crate::EntryId;
+use crate::Error;
 use crate::Wad;
 use crate::WadSlice;
 
@@ -20,13 +21,41 @@ impl<'a> Iterator for EntryIterator<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         if self.index < self.wad.len() {
             self.index += 1;
-            return Some(unsafe {
+            Some(unsafe {
                 // This is safe because entry_unchecked only elides the bounds
                 // check, and we do bounds checking in this function
                 self.wad.entry_unchecked(self.index - 1).unwrap()
-            });
+            })
         } else {
-            return None;
+            None
+        }
+    }
+}
+
+pub struct TryEntryIterator<'a> {
+    index: usize,
+    wad: &'a Wad,
+}
+
+impl<'a> TryEntryIterator<'a> {
+    pub(crate) fn new<'b>(wad: &'b Wad) -> TryEntryIterator<'b> {
+        TryEntryIterator { index: 0, wad }
+    }
+}
+
+impl<'a> Iterator for TryEntryIterator<'a> {
+    type Item = Result<Entry<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index < self.wad.len() {
+            self.index += 1;
+            Some(unsafe {
+                // This is safe because entry_unchecked only elides the bounds
+                // check, and we do bounds checking in this function
+                self.wad.entry_unchecked(self.index - 1)
+            })
+        } else {
+            None
         }
     }
 }
@@ -48,13 +77,13 @@ impl<'a> Iterator for IdIterator<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         if self.index < self.wad.len() {
             self.index += 1;
-            return Some(unsafe {
+            Some(unsafe {
                 // This is safe because entry_unchecked only elides the bounds
                 // check, and we do bounds checking in this function
                 self.wad.entry_id_unchecked(self.index - 1)
-            });
+            })
         } else {
-            return None;
+            None
         }
     }
 }
@@ -76,13 +105,41 @@ impl<'a> Iterator for SliceEntryIterator<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         if self.index < self.wad.len() {
             self.index += 1;
-            return Some(unsafe {
+            Some(unsafe {
                 // This is safe because entry_unchecked only elides the bounds
                 // check, and we do bounds checking in this function
                 self.wad.entry_unchecked(self.index - 1).unwrap()
-            });
+            })
         } else {
-            return None;
+            None
+        }
+    }
+}
+
+pub struct SliceTryEntryIterator<'a> {
+    index: usize,
+    wad: &'a WadSlice<'a>,
+}
+
+impl<'a> SliceTryEntryIterator<'a> {
+    pub(crate) fn new<'b>(wad: &'b WadSlice) -> SliceTryEntryIterator<'b> {
+        SliceTryEntryIterator { index: 0, wad }
+    }
+}
+
+impl<'a> Iterator for SliceTryEntryIterator<'a> {
+    type Item = Result<Entry<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index < self.wad.len() {
+            self.index += 1;
+            Some(unsafe {
+                // This is safe because entry_unchecked only elides the bounds
+                // check, and we do bounds checking in this function
+                self.wad.entry_unchecked(self.index - 1)
+            })
+        } else {
+            None
         }
     }
 }
@@ -104,13 +161,105 @@ impl<'a> Iterator for SliceIdIterator<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         if self.index < self.wad.len() {
             self.index += 1;
-            return Some(unsafe {
+            Some(unsafe {
                 // This is safe because entry_unchecked only elides the bounds
                 // check, and we do bounds checking in this function
                 self.wad.entry_id_unchecked(self.index - 1)
-            });
+            })
         } else {
-            return None;
+            None
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    fn wad_with_invalid_entry() -> Wad {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        builder.push(b"A", vec![1]);
+        builder.push(b"B", vec![2]);
+        builder.push(b"C", vec![3]);
+        let mut data = builder.to_bytes().unwrap();
+
+        // Make the length of the second entry negative
+        let directory_offset = data.len() - 3 * 16;
+        data[directory_offset + 16 + 7] = 0x80;
+
+        parse_wad(data).unwrap()
+    }
+
+    #[test]
+    fn try_entry_iter_reports_invalid_entries() {
+        let wad = wad_with_invalid_entry();
+        let entries: Vec<_> = wad.try_entry_iter().collect();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].as_ref().unwrap().lump, &[1]);
+        assert!(entries[1].is_err());
+        assert_eq!(entries[2].as_ref().unwrap().lump, &[3]);
+    }
+
+    #[test]
+    fn invalid_entry_error_has_context() {
+        let wad = wad_with_invalid_entry();
+        match wad.entry(1) {
+            Err(Error::InvalidEntry {
+                index,
+                id,
+                length,
+                directory_offset,
+                file_length,
+                ..
+            }) => {
+                assert_eq!(index, Some(1));
+                assert_eq!(id, EntryId::from(b"B"));
+                assert!(length < 0);
+                assert_eq!(directory_offset, 15);
+                assert_eq!(file_length, 15 + 3 * 16);
+            }
+            _ => panic!("Expected InvalidEntry"),
+        }
+
+        let message = wad
+            .try_entry_iter()
+            .nth(1)
+            .unwrap()
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(message.contains("entry 1 (B)"), "{}", message);
+
+        let mut builder = WadBuilder::new(Kind::PWad);
+        builder.push(b"A\x80", vec![1]);
+        let mut data = builder.to_bytes().unwrap();
+        let directory_offset = data.len() - 16;
+        data[directory_offset + 7] = 0x80;
+        let message = parse_wad(data).unwrap().entry(0).err().unwrap().to_string();
+        assert!(message.contains("entry 0 (A\\x80)"), "{}", message);
+    }
+
+    #[test]
+    fn slice_try_entry_iter_reports_invalid_entries() {
+        let wad = wad_with_invalid_entry();
+        let slice = wad.slice(1..);
+        let entries: Vec<_> = slice.try_entry_iter().collect();
+        assert!(entries[0].is_err());
+        assert_eq!(entries[1].as_ref().unwrap().lump, &[3]);
+
+        // Indices in errors from a slice are relative to the slice
+        match slice.entry(0) {
+            Err(Error::InvalidEntry { index, .. }) => assert_eq!(index, Some(0)),
+            _ => panic!("Expected InvalidEntry"),
+        }
+    }
+
+    #[test]
+    fn lump_does_not_panic() {
+        let wad = wad_with_invalid_entry();
+        assert_eq!(wad.lump(0).unwrap(), &[1]);
+        assert!(wad.lump(1).is_err());
+        assert!(wad.lump(3).is_err());
+        assert!(wad.slice(1..).lump(0).is_err());
+    }
+}
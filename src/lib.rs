@@ -1,15 +1,49 @@
 #[macro_use]
 mod error;
 
+mod compact;
+mod content_type;
+mod diff;
 mod entry;
 mod entry_id;
+mod entry_id_pattern;
+mod identify;
 mod iterator;
+mod manifest;
+mod map;
+mod namespace;
+mod parse_options;
+mod query;
+mod validate;
 mod wad;
+mod wad_builder;
+mod wad_index;
+mod wad_mut;
+mod wad_reader;
 mod wad_slice;
+mod wad_stack;
+mod wadinfo;
 
+pub use crate::compact::*;
+pub use crate::content_type::*;
+pub use crate::diff::*;
 pub use crate::entry::*;
 pub use crate::entry_id::*;
+pub use crate::entry_id_pattern::*;
 pub use crate::error::*;
+pub use crate::identify::*;
 pub use crate::iterator::*;
+pub use crate::manifest::*;
+pub use crate::map::*;
+pub use crate::namespace::*;
+pub use crate::parse_options::*;
+pub use crate::query::*;
+pub use crate::validate::*;
 pub use crate::wad::*;
+pub use crate::wad_builder::*;
+pub use crate::wad_index::*;
+pub use crate::wad_mut::*;
+pub use crate::wad_reader::*;
 pub use crate::wad_slice::*;
+pub use crate::wad_stack::*;
+pub use crate::wadinfo::*;
@@ -0,0 +1,770 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Component, Path};
+
+use crate::entry_id::{EntryId, EntryIdError};
+use crate::error::Error;
+use crate::map::BINARY_MAP_LUMPS;
+use crate::namespace::Namespace;
+use crate::wad::*;
+use crate::wad_builder::WadBuilder;
+use crate::wad_slice::WadSlice;
+
+/// The file name `wad-extract` writes the manifest to
+pub const MANIFEST_FILE_NAME: &str = "manifest.txt";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub id: EntryId,
+
+    /// Path of the file with the lump data, relative to the manifest and
+    /// with `/` as separator. `None` for zero-length lumps, such as map
+    /// headers and namespace markers. Absolute paths and `..` are rejected,
+    /// so a manifest can't refer to files outside its directory.
+    pub path: Option<String>,
+}
+
+/// The ordered list of entries in a WAD, with the location of the lump data
+/// of each entry in a directory tree.
+///
+/// As text, the first line is the kind, `IWAD` or `PWAD`, followed by one
+/// line per entry with the escaped name and optionally the path, separated
+/// by whitespace:
+///
+/// ```text
+/// PWAD
+/// E1M1
+/// THINGS maps/E1M1/THINGS.lmp
+/// ```
+///
+/// Names are escaped with `EntryId::escaped`, so that any entry ID can be
+/// represented.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub kind: Kind,
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Debug)]
+pub enum ManifestError {
+    /// The manifest does not start with `IWAD` or `PWAD`
+    InvalidKind(String),
+
+    /// The line does not consist of a name and an optional path
+    InvalidLine { line: usize, text: String },
+
+    /// The name on the line is not a valid escaped entry ID
+    InvalidName {
+        line: usize,
+        name: String,
+        reason: EntryIdError,
+    },
+
+    /// The path on the line is absolute or contains `..`
+    UnsafePath { line: usize, path: String },
+}
+
+impl std::fmt::Display for ManifestError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ManifestError::InvalidKind(x) => write!(fmt, "Invalid WAD kind in manifest: {:?}", x),
+            ManifestError::InvalidLine { line, text } => {
+                write!(fmt, "Invalid manifest line {}: {:?}", line, text)
+            }
+            ManifestError::InvalidName { line, name, reason } => write!(
+                fmt,
+                "Invalid lump name on manifest line {}: {:?}: {}",
+                line, name, reason
+            ),
+            ManifestError::UnsafePath { line, path } => write!(
+                fmt,
+                "Path outside of the manifest directory on manifest line {}: {:?}",
+                line, path
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+/// Directories for lumps in well-known namespaces. Other namespaces use
+/// their name in lowercase.
+const NAMESPACE_DIRECTORIES: &[(&str, &str)] = &[
+    ("A", "acs"),
+    ("C", "colormaps"),
+    ("F", "flats"),
+    ("HI", "hires"),
+    ("P", "patches"),
+    ("S", "sprites"),
+    ("TX", "textures"),
+    ("V", "voices"),
+];
+
+/// Directory for the lumps of maps, in subdirectories named after the map
+pub const MAPS_DIRECTORY: &str = "maps";
+
+pub(crate) fn namespace_directory(name: &str) -> String {
+    match NAMESPACE_DIRECTORIES.iter().find(|(ns, _)| *ns == name) {
+        Some((_, dir)) => dir.to_string(),
+        None => escape_file_name(name.as_bytes()).to_ascii_lowercase(),
+    }
+}
+
+/// The namespace name for a directory, the inverse of `namespace_directory`
+pub(crate) fn directory_namespace(dir: &str) -> Option<String> {
+    match NAMESPACE_DIRECTORIES.iter().find(|(_, d)| *d == dir) {
+        Some((ns, _)) => Some(ns.to_string()),
+        None => {
+            let name = String::from_utf8(unescape_file_name(dir)?).ok()?;
+            Some(name.to_ascii_uppercase())
+        }
+    }
+}
+
+fn is_file_name_safe(x: u8) -> bool {
+    x.is_ascii_alphanumeric() || b"_-[]^~+=(){}!@$".contains(&x)
+}
+
+/// Device names that can't be used as file names on Windows
+const RESERVED_FILE_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Escape a name for use as a file or directory name on any common file
+/// system. Unsafe bytes are written as `%NN`. The result never contains
+/// `.`, so it can be followed by a suffix.
+pub(crate) fn escape_file_name(name: &[u8]) -> String {
+    let reserved = RESERVED_FILE_NAMES
+        .iter()
+        .any(|x| x.as_bytes().eq_ignore_ascii_case(name));
+
+    let mut escaped = String::new();
+    for (i, &x) in name.iter().enumerate() {
+        if is_file_name_safe(x) && !(reserved && i == 0) {
+            escaped.push(x as char);
+        } else {
+            escaped.push_str(&format!("%{:02X}", x));
+        }
+    }
+
+    if escaped.is_empty() {
+        escaped.push_str("%00");
+    }
+
+    escaped
+}
+
+/// The inverse of `escape_file_name`
+pub(crate) fn unescape_file_name(s: &str) -> Option<Vec<u8>> {
+    let mut name = vec![];
+    let mut bytes = s.bytes();
+    while let Some(x) = bytes.next() {
+        name.push(match x {
+            b'%' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?
+            }
+            x if is_file_name_safe(x) => x,
+            _ => return None,
+        });
+    }
+    Some(name)
+}
+
+fn entry_id_from_bytes(name: &[u8]) -> Option<EntryId> {
+    let mut buf = [0u8; 8];
+    buf.get_mut(..name.len())?.copy_from_slice(name);
+    Some(EntryId::from_bytes(&buf))
+}
+
+/// The entry ID and duplicate number of a lump file name as chosen by
+/// `Manifest::new`, such as `DEMO1.lmp` or `DEMO1.1.lmp`. `None` if the file
+/// is not a lump file.
+fn parse_lump_file_name(file_name: &str) -> Option<io::Result<(EntryId, usize)>> {
+    let stem = file_name.strip_suffix(".lmp")?;
+
+    let parse = || {
+        let (name, n) = match stem.split_once('.') {
+            Some((name, n)) => (name, n.parse().ok()?),
+            None => (stem, 0),
+        };
+        Some((entry_id_from_bytes(&unescape_file_name(name)?)?, n))
+    };
+
+    Some(parse().ok_or_else(|| invalid_data(format!("Invalid lump file name: {:?}", file_name))))
+}
+
+/// Whether `path` is relative and stays within the directory it is
+/// relative to. Backslashes count as separators, as they do on Windows.
+fn is_contained_path(path: &str) -> bool {
+    let components_ok = Path::new(path)
+        .components()
+        .all(|x| matches!(x, Component::Normal(_) | Component::CurDir));
+    components_ok && !path.starts_with('\\') && !path.split(['/', '\\']).any(|x| x == "..")
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) struct DirectoryContents {
+    /// Lump files with their ID, sorted by ID and duplicate number
+    pub files: Vec<(EntryId, String)>,
+
+    /// Names of subdirectories, sorted
+    pub directories: Vec<String>,
+}
+
+/// List the directory `dir` within `root`. `dir` is empty or ends with `/`.
+pub(crate) fn read_directory(root: &Path, dir: &str) -> io::Result<DirectoryContents> {
+    let mut files = vec![];
+    let mut directories = vec![];
+
+    for entry in std::fs::read_dir(root.join(dir))? {
+        let entry = entry?;
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| invalid_data(format!("Invalid file name: {:?}", name)))?;
+
+        if entry.file_type()?.is_dir() {
+            directories.push(name);
+        } else if let Some(parsed) = parse_lump_file_name(&name) {
+            let (id, n) = parsed?;
+            files.push(((*id.as_bytes(), n), id, format!("{}{}", dir, name)));
+        }
+    }
+
+    files.sort_unstable_by_key(|(key, ..)| *key);
+    directories.sort_unstable();
+
+    Ok(DirectoryContents {
+        files: files.into_iter().map(|(_, id, path)| (id, path)).collect(),
+        directories,
+    })
+}
+
+/// Sort key for putting the lumps of a map in the usual order
+fn map_lump_order(id: &EntryId) -> usize {
+    let is = |name: &[u8; 8]| id.matches(&EntryId::from_bytes(name));
+    if is(b"TEXTMAP\0") {
+        0
+    } else if is(b"ENDMAP\0\0") {
+        usize::MAX
+    } else {
+        match BINARY_MAP_LUMPS.iter().position(|name| is(name)) {
+            Some(position) => 1 + position,
+            None => BINARY_MAP_LUMPS.len() + 1,
+        }
+    }
+}
+
+pub(crate) fn push_map(
+    root: &Path,
+    name: &str,
+    entries: &mut Vec<ManifestEntry>,
+) -> io::Result<()> {
+    let map_name = unescape_file_name(name)
+        .and_then(|name| entry_id_from_bytes(&name))
+        .ok_or_else(|| invalid_data(format!("Invalid map directory name: {:?}", name)))?;
+
+    let mut lumps = read_directory(root, &format!("{}/{}/", MAPS_DIRECTORY, name))?.files;
+
+    let header = lumps.iter().position(|(id, _)| *id == map_name);
+    let header_path = header.map(|index| lumps.remove(index).1);
+    entries.push(ManifestEntry {
+        id: map_name,
+        path: header_path,
+    });
+
+    lumps.sort_by_key(|(id, _)| map_lump_order(id));
+
+    let is_udmf = lumps.iter().any(|(id, _)| map_lump_order(id) == 0);
+    let has_end = lumps.iter().any(|(id, _)| map_lump_order(id) == usize::MAX);
+
+    entries.extend(lumps.into_iter().map(|(id, path)| ManifestEntry {
+        id,
+        path: Some(path),
+    }));
+
+    if is_udmf && !has_end {
+        entries.push(ManifestEntry {
+            id: EntryId::from_bytes(b"ENDMAP\0\0"),
+            path: None,
+        });
+    }
+
+    Ok(())
+}
+
+fn push_namespace(
+    root: &Path,
+    parent: &str,
+    name: &str,
+    entries: &mut Vec<ManifestEntry>,
+) -> io::Result<()> {
+    let namespace = directory_namespace(name);
+    let marker = |suffix: &str| {
+        let marker = format!("{}{}", namespace.as_deref()?, suffix);
+        EntryId::from_str(marker)
+    };
+    let invalid = || invalid_data(format!("Invalid namespace directory name: {:?}", name));
+    let start = marker("_START").ok_or_else(invalid)?;
+    let end = marker("_END").ok_or_else(invalid)?;
+
+    let dir = format!("{}{}/", parent, name);
+    let contents = read_directory(root, &dir)?;
+
+    entries.push(ManifestEntry {
+        id: start,
+        path: None,
+    });
+    entries.extend(contents.files.into_iter().map(|(id, path)| ManifestEntry {
+        id,
+        path: Some(path),
+    }));
+    for child in &contents.directories {
+        push_namespace(root, &dir, child, entries)?;
+    }
+    entries.push(ManifestEntry {
+        id: end,
+        path: None,
+    });
+
+    Ok(())
+}
+
+/// The file name for a lump, without directory and suffix. Anything after
+/// the first NUL is left out, since it is preserved in the manifest.
+pub(crate) fn lump_file_name(id: &EntryId) -> String {
+    let bytes = id.as_bytes();
+    let name = &bytes[..bytes.iter().position(|&x| x == 0).unwrap_or(8)];
+    escape_file_name(name)
+}
+
+/// The path for a lump in `dir`, which is empty or ends with `/`, with a
+/// numbered suffix if the path is already in `used`. Paths are compared case
+/// insensitively.
+pub(crate) fn unique_lump_path(used: &mut HashSet<String>, dir: &str, id: &EntryId) -> String {
+    let base = format!("{}{}", dir, lump_file_name(id));
+    let path = (0..)
+        .map(|n| match n {
+            0 => format!("{}.lmp", base),
+            n => format!("{}.{}.lmp", base, n),
+        })
+        .find(|path| !used.contains(&path.to_ascii_lowercase()))
+        .unwrap();
+    used.insert(path.to_ascii_lowercase());
+    path
+}
+
+fn assign_namespace_directories(namespaces: &[Namespace], parent: &str, dirs: &mut [String]) {
+    for namespace in namespaces {
+        let dir = format!("{}{}/", parent, namespace_directory(&namespace.name));
+        let start = namespace.entries_start();
+        for entry_dir in &mut dirs[start..start + namespace.entries.len()] {
+            entry_dir.clone_from(&dir);
+        }
+        assign_namespace_directories(&namespace.children, &dir, dirs);
+    }
+}
+
+impl Manifest {
+    /// Lay out the lumps of `wad` in a directory tree. Lumps in namespaces
+    /// go in a directory for the namespace, such as `sprites/TROOA1.lmp`,
+    /// and map lumps go in a directory for the map, such as
+    /// `maps/E1M1/THINGS.lmp`. Other lumps go in the root directory.
+    ///
+    /// Lumps with the same name in the same directory get a numbered suffix,
+    /// such as `DEMO1.1.lmp`. Case is preserved, but names that differ only
+    /// in case are treated as the same for case-insensitive file systems.
+    ///
+    /// Fails if any directory entry is invalid.
+    pub fn new(kind: Kind, wad: &WadSlice) -> Result<Manifest, Error> {
+        let mut dirs = vec![String::new(); wad.len()];
+        assign_namespace_directories(&wad.namespaces(), "", &mut dirs);
+        for map in wad.maps() {
+            let dir = format!("{}/{}/", MAPS_DIRECTORY, lump_file_name(&map.name));
+            for entry_dir in &mut dirs[map.index..map.index + 1 + map.lumps.len()] {
+                entry_dir.clone_from(&dir);
+            }
+        }
+
+        let mut used = HashSet::new();
+        let mut entries = vec![];
+        for (index, dir) in dirs.iter().enumerate() {
+            let entry = wad.entry(index)?;
+
+            let path = if entry.lump.is_empty() {
+                None
+            } else {
+                Some(unique_lump_path(&mut used, dir, &entry.id))
+            };
+
+            entries.push(ManifestEntry { id: entry.id, path });
+        }
+
+        Ok(Manifest { kind, entries })
+    }
+
+    /// Infer the entries from a directory tree laid out like `Manifest::new`
+    /// does, for when there is no manifest.
+    ///
+    /// The lumps in the root directory come first, ordered by name. Then
+    /// come the maps in `maps`, ordered by name, each with a header lump
+    /// followed by the map lumps in the usual order. `ENDMAP` is added to
+    /// UDMF maps that lack it. Then come the namespaces in the other
+    /// directories, ordered by directory name, each with `_START` and
+    /// `_END` markers around its lumps and nested namespaces.
+    ///
+    /// Files not ending in `.lmp` are ignored.
+    pub fn from_directory(kind: Kind, root: &Path) -> io::Result<Manifest> {
+        let contents = read_directory(root, "")?;
+
+        let mut entries: Vec<_> = contents
+            .files
+            .into_iter()
+            .map(|(id, path)| ManifestEntry {
+                id,
+                path: Some(path),
+            })
+            .collect();
+
+        if contents.directories.iter().any(|dir| dir == MAPS_DIRECTORY) {
+            for map in read_directory(root, &format!("{}/", MAPS_DIRECTORY))?.directories {
+                push_map(root, &map, &mut entries)?;
+            }
+        }
+
+        for dir in &contents.directories {
+            if dir != MAPS_DIRECTORY {
+                push_namespace(root, "", dir, &mut entries)?;
+            }
+        }
+
+        Ok(Manifest { kind, entries })
+    }
+
+    /// Read the lump files, with paths relative to `root`, into a
+    /// `WadBuilder`. For a manifest from `Manifest::new`, the builder writes
+    /// a WAD file identical to the original if the original had no unused
+    /// space and its lumps were stored in directory order.
+    pub fn to_builder(&self, root: &Path) -> io::Result<WadBuilder> {
+        let mut builder = WadBuilder::new(self.kind);
+        for entry in &self.entries {
+            let lump = match &entry.path {
+                Some(path) => {
+                    if !is_contained_path(path) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Path outside of the manifest directory: {:?}", path),
+                        ));
+                    }
+                    let path = root.join(path);
+                    std::fs::read(&path).map_err(|e| {
+                        io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+                    })?
+                }
+                None => vec![],
+            };
+            builder.push(entry.id, lump);
+        }
+        Ok(builder)
+    }
+
+    pub fn parse(text: &str) -> Result<Manifest, ManifestError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        let kind = match lines.next() {
+            Some((_, "IWAD")) => Kind::IWad,
+            Some((_, "PWAD")) => Kind::PWad,
+            Some((_, x)) => return Err(ManifestError::InvalidKind(x.to_string())),
+            None => return Err(ManifestError::InvalidKind(String::new())),
+        };
+
+        let mut entries = vec![];
+        for (line, text) in lines {
+            let fields: Vec<_> = text.split_whitespace().collect();
+            let (name, path) = match fields[..] {
+                [name] => (name, None),
+                [name, path] => (name, Some(path.to_string())),
+                _ => {
+                    return Err(ManifestError::InvalidLine {
+                        line,
+                        text: text.to_string(),
+                    })
+                }
+            };
+
+            let id = EntryId::from_escaped(name).map_err(|reason| ManifestError::InvalidName {
+                line,
+                name: name.to_string(),
+                reason,
+            })?;
+
+            if let Some(path) = &path {
+                if !is_contained_path(path) {
+                    return Err(ManifestError::UnsafePath {
+                        line,
+                        path: path.to_string(),
+                    });
+                }
+            }
+
+            entries.push(ManifestEntry { id, path });
+        }
+
+        Ok(Manifest { kind, entries })
+    }
+}
+
+impl std::str::FromStr for Manifest {
+    type Err = ManifestError;
+
+    fn from_str(s: &str) -> Result<Manifest, ManifestError> {
+        Manifest::parse(s)
+    }
+}
+
+impl std::fmt::Display for Manifest {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(fmt, "{}", String::from_utf8_lossy(self.kind.magic()))?;
+        for entry in &self.entries {
+            write!(fmt, "{}", entry.id.escaped())?;
+            if let Some(path) = &entry.path {
+                write!(fmt, " {}", path)?;
+            }
+            writeln!(fmt)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    fn wad(lumps: &[(&[u8], &str)]) -> Wad {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        for &(name, lump) in lumps {
+            let mut id = [0; 8];
+            id[..name.len()].copy_from_slice(name);
+            builder.push(EntryId::from_bytes(&id), lump.as_bytes());
+        }
+        parse_wad(builder.to_bytes().unwrap()).unwrap()
+    }
+
+    fn paths(manifest: &Manifest) -> Vec<Option<&str>> {
+        manifest
+            .entries
+            .iter()
+            .map(|entry| entry.path.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn lays_out_namespaces_and_maps() {
+        let wad = wad(&[
+            (b"PLAYPAL", "pal"),
+            (b"E1M1", ""),
+            (b"THINGS", "t"),
+            (b"S_START", ""),
+            (b"TROOA1", "troo"),
+            (b"S_END", ""),
+            (b"FF_START", ""),
+            (b"F1_START", ""),
+            (b"FLOOR0_1", "floor"),
+            (b"F1_END", ""),
+            (b"FF_END", ""),
+            (b"ZZ_START", ""),
+            (b"THING", "x"),
+        ]);
+        let manifest = Manifest::new(Kind::PWad, &wad.as_slice()).unwrap();
+        assert_eq!(
+            paths(&manifest),
+            [
+                Some("PLAYPAL.lmp"),
+                None,
+                Some("maps/E1M1/THINGS.lmp"),
+                None,
+                Some("sprites/TROOA1.lmp"),
+                None,
+                None,
+                None,
+                Some("flats/f1/FLOOR0_1.lmp"),
+                None,
+                None,
+                None,
+                Some("z/THING.lmp"),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicates_get_unique_paths() {
+        let wad = wad(&[(b"DEMO1", "a"), (b"DEMO1", "b"), (b"demo1", "c")]);
+        let manifest = Manifest::new(Kind::PWad, &wad.as_slice()).unwrap();
+        assert_eq!(
+            paths(&manifest),
+            [Some("DEMO1.lmp"), Some("DEMO1.1.lmp"), Some("demo1.2.lmp")]
+        );
+    }
+
+    #[test]
+    fn escapes_hostile_file_names() {
+        let id = |name: &[u8; 8]| lump_file_name(&EntryId::from_bytes(name));
+        assert_eq!(id(b"VILE\\1\0\0"), "VILE%5C1");
+        assert_eq!(id(b"../..\0\0\0"), "%2E%2E%2F%2E%2E");
+        assert_eq!(id(b"E1M1\x80\0\0\0"), "E1M1%80");
+        assert_eq!(id(b"CON\0\0\0\0\0"), "%43ON");
+        assert_eq!(id(b"\0\0\0\0\0\0\0\0"), "%00");
+
+        for name in &["VILE%5C1", "%2E%2E", "%43ON", "%00"] {
+            let unescaped = unescape_file_name(name).unwrap();
+            assert_eq!(escape_file_name(&unescaped), *name);
+        }
+        assert_eq!(unescape_file_name("A.B"), None);
+    }
+
+    #[test]
+    fn roundtrips_as_text() {
+        let wad = wad(&[(b"E1M1", ""), (b"THINGS", "t"), (b"a b\0junk", "x")]);
+        let manifest = Manifest::new(Kind::IWad, &wad.as_slice()).unwrap();
+        let text = manifest.to_string();
+        assert!(text.starts_with("IWAD\nE1M1\nTHINGS maps/E1M1/THINGS.lmp\n"));
+        assert_eq!(Manifest::parse(&text).unwrap(), manifest);
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("wad-manifest-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn write_files(wad: &Wad, manifest: &Manifest, root: &Path) {
+        for (index, entry) in manifest.entries.iter().enumerate() {
+            if let Some(path) = &entry.path {
+                let path = root.join(path);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, wad.lump(index).unwrap()).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn roundtrips_through_files() {
+        let mut builder = WadBuilder::new(Kind::IWad);
+        for &(name, lump) in &[
+            (&b"DEMO1"[..], "a"),
+            (b"DEMO1", "b"),
+            (b"PLAYPAL", "p"),
+            (b"E1M1", ""),
+            (b"THINGS", "t"),
+            (b"LINEDEFS", "l"),
+            (b"MAP01", ""),
+            (b"TEXTMAP", "x"),
+            (b"ZNODES", "z"),
+            (b"ENDMAP", ""),
+            (b"F_START", ""),
+            (b"FLOOR0_1", "f"),
+            (b"F1_START", ""),
+            (b"FLOOR1", "g"),
+            (b"F1_END", ""),
+            (b"F_END", ""),
+            (b"S_START", ""),
+            (b"VILE\\1", "v"),
+            (b"S_END", ""),
+        ] {
+            builder.push(entry_id_from_bytes(name).unwrap(), lump.as_bytes());
+        }
+        let data = builder.to_bytes().unwrap();
+        let wad = parse_wad(data.clone()).unwrap();
+
+        let root = temp_dir("roundtrip");
+        let manifest = Manifest::new(Kind::IWad, &wad.as_slice()).unwrap();
+        write_files(&wad, &manifest, &root);
+
+        let manifest = Manifest::parse(&manifest.to_string()).unwrap();
+        let packed = manifest.to_builder(&root).unwrap().to_bytes().unwrap();
+        assert_eq!(packed, data);
+
+        // The WAD is in the order that is inferred from the layout
+        let inferred = Manifest::from_directory(Kind::IWad, &root).unwrap();
+        assert_eq!(inferred, manifest);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn infers_map_order() {
+        let root = temp_dir("map-order");
+        let dir = root.join("maps").join("MAP01");
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in &["MAP01", "ZNODES", "TEXTMAP"] {
+            std::fs::write(dir.join(format!("{}.lmp", name)), name).unwrap();
+        }
+        std::fs::write(root.join("README.txt"), "ignored").unwrap();
+
+        let manifest = Manifest::from_directory(Kind::PWad, &root).unwrap();
+        assert_eq!(
+            manifest.to_string(),
+            "PWAD\nMAP01 maps/MAP01/MAP01.lmp\nTEXTMAP maps/MAP01/TEXTMAP.lmp\n\
+             ZNODES maps/MAP01/ZNODES.lmp\nENDMAP\n"
+        );
+
+        std::fs::write(root.join("bad.name.lmp"), "").unwrap();
+        let err = Manifest::from_directory(Kind::PWad, &root).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_manifests() {
+        assert!(matches!(
+            Manifest::parse("ZWAD\n"),
+            Err(ManifestError::InvalidKind(_))
+        ));
+        assert!(matches!(
+            Manifest::parse("PWAD\nA b c\n"),
+            Err(ManifestError::InvalidLine { line: 2, .. })
+        ));
+        assert!(matches!(
+            Manifest::parse("PWAD\n\nTOOLONGNAME\n"),
+            Err(ManifestError::InvalidName { line: 3, .. })
+        ));
+        for path in &[
+            "/etc/passwd",
+            "../../etc/passwd",
+            "maps/../../x",
+            "..\\x",
+            "\\x",
+        ] {
+            assert!(matches!(
+                Manifest::parse(&format!("PWAD\nA {}\n", path)),
+                Err(ManifestError::UnsafePath { line: 2, .. })
+            ));
+        }
+        assert!(Manifest::parse("PWAD\nA ./maps/E1M1/A.lmp\n").is_ok());
+    }
+
+    #[test]
+    fn to_builder_rejects_paths_outside_root() {
+        let manifest = Manifest {
+            kind: Kind::PWad,
+            entries: vec![ManifestEntry {
+                id: EntryId::from_str("A").unwrap(),
+                path: Some("../secret.lmp".to_string()),
+            }],
+        };
+        let err = manifest.to_builder(&temp_dir("unsafe")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
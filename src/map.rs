@@ -0,0 +1,221 @@
+use crate::entry_id::EntryId;
+use crate::wad_slice::WadSlice;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MapFormat {
+    /// Binary map lumps from `THINGS` through `BLOCKMAP`
+    Doom,
+
+    /// Binary map lumps like `Doom`, with the addition of `BEHAVIOR`
+    Hexen,
+
+    /// Textual map format, `TEXTMAP` through `ENDMAP`
+    Udmf,
+}
+
+/// A map in a WAD, identified by its header lump, which may have any name.
+#[derive(Clone)]
+pub struct MapRef<'a> {
+    /// The name of the map, which is the ID of the header lump
+    pub name: EntryId,
+
+    /// Index of the header lump
+    pub index: usize,
+
+    pub format: MapFormat,
+
+    /// The lumps following the header that belong to this map. Index `i`
+    /// in `lumps` is index `index + 1 + i` in the directory.
+    pub lumps: WadSlice<'a>,
+}
+
+/// The lumps of a binary map, in the order they are usually stored
+pub(crate) const BINARY_MAP_LUMPS: &[&[u8; 8]] = &[
+    b"THINGS\0\0",
+    b"LINEDEFS",
+    b"SIDEDEFS",
+    b"VERTEXES",
+    b"SEGS\0\0\0\0",
+    b"SSECTORS",
+    b"NODES\0\0\0",
+    b"SECTORS\0",
+    b"REJECT\0\0",
+    b"BLOCKMAP",
+    b"BEHAVIOR",
+    b"SCRIPTS\0",
+];
+
+pub(crate) fn is(id: EntryId, name: &[u8; 8]) -> bool {
+    id.matches(&EntryId::from_bytes(name))
+}
+
+/// Number of lumps after the header that belong to a binary map, if the
+/// header at `index` starts one
+fn binary_map_length(wad: &WadSlice, index: usize) -> Option<(usize, MapFormat)> {
+    // The first lump of a binary map is always THINGS
+    if !is(wad.entry_id(index + 1)?, BINARY_MAP_LUMPS[0]) {
+        return None;
+    }
+
+    // Any other map lumps may follow, but a repeated lump belongs to the
+    // next map
+    let mut seen = [false; BINARY_MAP_LUMPS.len()];
+    let mut length = 0;
+    for id in wad.id_iter().skip(index + 1) {
+        match BINARY_MAP_LUMPS.iter().position(|name| is(id, name)) {
+            Some(lump) if !seen[lump] => seen[lump] = true,
+            _ => break,
+        }
+        length += 1;
+    }
+
+    let lumps = wad.slice(index + 1..index + 1 + length);
+    let format = if lumps.index_of(b"BEHAVIOR").is_some() {
+        MapFormat::Hexen
+    } else {
+        MapFormat::Doom
+    };
+    Some((length, format))
+}
+
+/// Number of lumps after the header that belong to a UDMF map, if the header
+/// at `index` starts one. A map without `ENDMAP` is not taken as a map. The
+/// search for `ENDMAP` stops at the start of the next map, which is
+/// recognized by its `TEXTMAP` or `THINGS` lump.
+fn udmf_map_length(wad: &WadSlice, index: usize) -> Option<usize> {
+    if !is(wad.entry_id(index + 1)?, b"TEXTMAP\0") {
+        return None;
+    }
+
+    for (length, id) in wad.id_iter().skip(index + 2).enumerate() {
+        if is(id, b"ENDMAP\0\0") {
+            return Some(length + 2);
+        }
+        if is(id, b"TEXTMAP\0") || is(id, BINARY_MAP_LUMPS[0]) {
+            return None;
+        }
+    }
+
+    None
+}
+
+pub(crate) fn find_maps<'a>(wad: &WadSlice<'a>) -> Vec<MapRef<'a>> {
+    let mut maps = vec![];
+
+    let mut index = 0;
+    while index < wad.len() {
+        let map = if let Some(length) = udmf_map_length(wad, index) {
+            Some((length, MapFormat::Udmf))
+        } else {
+            binary_map_length(wad, index)
+        };
+
+        match map {
+            Some((length, format)) => {
+                maps.push(MapRef {
+                    name: wad.entry_id(index).unwrap(),
+                    index,
+                    format,
+                    lumps: wad.slice(index + 1..index + 1 + length),
+                });
+                index += 1 + length;
+            }
+            None => index += 1,
+        }
+    }
+
+    maps
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    fn wad(names: &[&str]) -> Wad {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        for name in names {
+            builder.push(EntryId::from_str(name).unwrap(), vec![]);
+        }
+        parse_wad(builder.to_bytes().unwrap()).unwrap()
+    }
+
+    fn summary(wad: &Wad) -> Vec<(String, usize, MapFormat, usize)> {
+        wad.as_slice()
+            .maps()
+            .iter()
+            .map(|m| (m.name.to_string(), m.index, m.format, m.lumps.len()))
+            .collect()
+    }
+
+    #[test]
+    fn finds_doom_maps() {
+        let wad = wad(&[
+            "PLAYPAL", "E1M1", "THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SEGS", "SSECTORS",
+            "NODES", "SECTORS", "REJECT", "BLOCKMAP", "E1M2", "THINGS", "LINEDEFS",
+        ]);
+        assert_eq!(
+            summary(&wad),
+            [
+                ("E1M1".to_string(), 1, MapFormat::Doom, 10),
+                ("E1M2".to_string(), 12, MapFormat::Doom, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn map_does_not_extend_into_next_map() {
+        // The second map has no header of its own. It must not be taken as
+        // part of the first.
+        let wad = wad(&["MAP01", "THINGS", "LINEDEFS", "THINGS", "LINEDEFS"]);
+        let maps = wad.as_slice().maps();
+        assert_eq!(maps.len(), 1);
+        assert_eq!(maps[0].lumps.len(), 2);
+        assert_eq!(maps[0].lumps.index_of(b"LINEDEFS"), Some(1));
+    }
+
+    #[test]
+    fn finds_hexen_and_udmf_maps() {
+        let wad = wad(&[
+            "MAP01", "THINGS", "LINEDEFS", "BEHAVIOR", "MYMAP", "TEXTMAP", "ZNODES", "ENDMAP",
+            "CREDITS",
+        ]);
+        assert_eq!(
+            summary(&wad),
+            [
+                ("MAP01".to_string(), 0, MapFormat::Hexen, 3),
+                ("MYMAP".to_string(), 4, MapFormat::Udmf, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_udmf_is_not_a_map() {
+        let wad = wad(&["MAP01", "TEXTMAP", "ZNODES"]);
+        assert!(wad.as_slice().maps().is_empty());
+    }
+
+    #[test]
+    fn unterminated_udmf_does_not_absorb_next_map() {
+        let first = wad(&[
+            "MAP01", "TEXTMAP", "ZNODES", "MAP02", "TEXTMAP", "ZNODES", "ENDMAP",
+        ]);
+        assert_eq!(
+            summary(&first),
+            [("MAP02".to_string(), 3, MapFormat::Udmf, 3)]
+        );
+
+        let second = wad(&["MAP01", "TEXTMAP", "MAP02", "THINGS", "LINEDEFS", "ENDMAP"]);
+        assert_eq!(
+            summary(&second),
+            [("MAP02".to_string(), 2, MapFormat::Doom, 2)]
+        );
+    }
+
+    #[test]
+    fn map_by_name() {
+        let wad = wad(&["E1M1", "THINGS", "E1M2", "THINGS", "LINEDEFS"]);
+        let map = wad.as_slice().map(b"e1m2").unwrap();
+        assert_eq!(map.index, 2);
+        assert!(wad.as_slice().map(b"E1M3").is_none());
+    }
+}
@@ -0,0 +1,220 @@
+use crate::entry_id::EntryId;
+use crate::wad_slice::WadSlice;
+
+/// A section of the directory delimited by `X_START` and `X_END` markers,
+/// such as `S_START`/`S_END` for sprites, `F_START`/`F_END` for flats and
+/// `P_START`/`P_END` for patches.
+#[derive(Clone)]
+pub struct Namespace<'a> {
+    /// The name of the namespace, uppercased. Doubled single letters, as
+    /// in the `SS_START` and `FF_START` markers used by PWADs, are
+    /// collapsed, so both `S_START` and `SS_START` give the name `"S"`.
+    pub name: String,
+
+    /// Index of the `_START` marker
+    pub start: usize,
+
+    /// Index of the `_END` marker, or `None` if the namespace is not
+    /// terminated. An unterminated namespace extends to the end of its
+    /// parent, or to the end of the directory.
+    pub end: Option<usize>,
+
+    /// The entries between the markers, including those of nested
+    /// namespaces. Index `i` in `entries` is index `start + 1 + i` in the
+    /// directory.
+    pub entries: WadSlice<'a>,
+
+    /// Namespaces nested within this one, such as `F1_START`/`F1_END`
+    /// within `F_START`/`F_END`
+    pub children: Vec<Namespace<'a>>,
+}
+
+impl<'a> Namespace<'a> {
+    pub fn is_terminated(&self) -> bool {
+        self.end.is_some()
+    }
+
+    /// Directory index of the first entry after the `_START` marker
+    pub fn entries_start(&self) -> usize {
+        self.start + 1
+    }
+}
+
+pub(crate) fn normalize_namespace_name(name: &str) -> String {
+    let name = name.to_ascii_uppercase();
+    let bytes = name.as_bytes();
+    if bytes.len() == 2 && bytes[0] == bytes[1] {
+        name[..1].to_string()
+    } else {
+        name
+    }
+}
+
+fn marker_name(id: EntryId, suffix: &str) -> Option<String> {
+    let id = id.normalized();
+    let name = id.display().strip_suffix(suffix)?;
+    if name.is_empty() {
+        return None;
+    }
+    Some(normalize_namespace_name(name))
+}
+
+struct Open<'a> {
+    name: String,
+    start: usize,
+    children: Vec<Namespace<'a>>,
+}
+
+fn close<'a>(wad: &WadSlice<'a>, open: Open<'a>, end: usize, terminated: bool) -> Namespace<'a> {
+    Namespace {
+        name: open.name,
+        start: open.start,
+        end: if terminated { Some(end) } else { None },
+        entries: wad.slice(open.start + 1..end),
+        children: open.children,
+    }
+}
+
+pub(crate) fn parse_namespaces<'a>(wad: &WadSlice<'a>) -> Vec<Namespace<'a>> {
+    let mut top_level = vec![];
+    let mut stack: Vec<Open<'a>> = vec![];
+
+    let mut finish = |stack: &mut Vec<Open<'a>>, namespace: Namespace<'a>| match stack.last_mut() {
+        Some(parent) => parent.children.push(namespace),
+        None => top_level.push(namespace),
+    };
+
+    for (index, id) in wad.id_iter().enumerate() {
+        if let Some(name) = marker_name(id, "_START") {
+            stack.push(Open {
+                name,
+                start: index,
+                children: vec![],
+            });
+        } else if let Some(name) = marker_name(id, "_END") {
+            // An end marker closes the innermost namespace with the same
+            // name. Namespaces nested inside that one are unterminated.
+            // End markers that match no open namespace are ignored.
+            if let Some(pos) = stack.iter().rposition(|open| open.name == name) {
+                while stack.len() > pos + 1 {
+                    let open = stack.pop().unwrap();
+                    let namespace = close(wad, open, index, false);
+                    finish(&mut stack, namespace);
+                }
+                let open = stack.pop().unwrap();
+                let namespace = close(wad, open, index, true);
+                finish(&mut stack, namespace);
+            }
+        }
+    }
+
+    while let Some(open) = stack.pop() {
+        let namespace = close(wad, open, wad.len(), false);
+        finish(&mut stack, namespace);
+    }
+
+    top_level
+}
+
+pub(crate) fn find_namespaces<'a, 'b>(
+    namespaces: &'b [Namespace<'a>],
+    name: &str,
+    found: &mut Vec<&'b Namespace<'a>>,
+) {
+    for namespace in namespaces {
+        if namespace.name == name {
+            found.push(namespace);
+        }
+        find_namespaces(&namespace.children, name, found);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    fn wad(names: &[&str]) -> Wad {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        for name in names {
+            builder.push(EntryId::from_str(name).unwrap(), vec![]);
+        }
+        parse_wad(builder.to_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn finds_flat_namespaces() {
+        let wad = wad(&[
+            "PLAYPAL", "S_START", "TROOA1", "S_END", "F_START", "FLOOR0_1", "F_END",
+        ]);
+        let namespaces = wad.as_slice().namespaces();
+        assert_eq!(namespaces.len(), 2);
+
+        assert_eq!(namespaces[0].name, "S");
+        assert_eq!((namespaces[0].start, namespaces[0].end), (1, Some(3)));
+        assert_eq!(namespaces[0].entries.len(), 1);
+        assert_eq!(
+            namespaces[0].entries.entry_id(0),
+            EntryId::from_str("TROOA1")
+        );
+
+        assert_eq!(namespaces[1].name, "F");
+        assert_eq!(namespaces[1].entries_start(), 5);
+    }
+
+    #[test]
+    fn finds_nested_namespaces() {
+        let wad = wad(&["F_START", "F1_START", "FLOOR0_1", "F1_END", "F_END"]);
+        let namespaces = wad.as_slice().namespaces();
+        assert_eq!(namespaces.len(), 1);
+        assert_eq!(namespaces[0].entries.len(), 3);
+        assert_eq!(namespaces[0].children.len(), 1);
+        assert_eq!(namespaces[0].children[0].name, "F1");
+        assert_eq!(namespaces[0].children[0].entries.len(), 1);
+    }
+
+    #[test]
+    fn doubled_letters_are_the_same_namespace() {
+        let wad = wad(&[
+            "FF_START", "FLOOR0_1", "F_END", "ss_start", "TROOA1", "SS_END",
+        ]);
+        let namespaces = wad.as_slice().namespaces();
+        assert_eq!(namespaces.len(), 2);
+        assert_eq!(namespaces[0].name, "F");
+        assert!(namespaces[0].is_terminated());
+        assert_eq!(namespaces[1].name, "S");
+
+        assert_eq!(wad.as_slice().namespace("SS").unwrap().start, 3);
+        assert_eq!(wad.as_slice().namespace("f").unwrap().start, 0);
+    }
+
+    #[test]
+    fn unterminated_namespaces() {
+        let wad = wad(&[
+            "F_START", "F1_START", "FLOOR0_1", "F_END", "P_START", "WALL00",
+        ]);
+        let namespaces = wad.as_slice().namespaces();
+        assert_eq!(namespaces.len(), 2);
+
+        let f1 = &namespaces[0].children[0];
+        assert_eq!(f1.end, None);
+        assert_eq!(f1.entries.len(), 1);
+
+        assert_eq!(namespaces[1].end, None);
+        assert_eq!(namespaces[1].entries.len(), 1);
+    }
+
+    #[test]
+    fn all_namespaces_searches_recursively() {
+        let wad = wad(&[
+            "F_START", "F1_START", "F1_END", "F_END", "FF_START", "FF_END",
+        ]);
+        let found: Vec<_> = wad
+            .as_slice()
+            .all_namespaces("F")
+            .iter()
+            .map(|n| n.start)
+            .collect();
+        assert_eq!(found, [0, 4]);
+        assert_eq!(wad.as_slice().all_namespaces("F1").len(), 1);
+    }
+}
@@ -0,0 +1,345 @@
+use std::path::Path;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::entry_id::EntryId;
+use crate::error::{Error, LoadError};
+use crate::wad::*;
+use crate::wad_slice::{lump_range, WadSlice};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Reject files whose directory does not fit in the file, like
+    /// `parse_wad`. Invalid entries are reported when they are accessed.
+    Strict,
+
+    /// Salvage what can be read from a damaged file. Directory entries are
+    /// read up to the end of the file, and every entry is checked up front
+    /// and fixed according to `ParseOptions::out_of_range`.
+    Recover,
+}
+
+/// What to do in `ParseMode::Recover` with an entry whose lump does not fit
+/// in the lump data
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutOfRange {
+    /// Shorten the lump so it ends where the lump data ends, if it starts
+    /// within the lump data. Entries that can't be shortened are dropped.
+    Clamp,
+
+    /// Drop the entry from the directory
+    Drop,
+}
+
+/// Options for `parse_wad_with_options`. The default is strict parsing,
+/// which behaves exactly like `parse_wad`.
+///
+/// Zero-length lumps are accepted regardless of their start offset in both
+/// modes, because some entries in official IWADs have start 0.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    pub mode: ParseMode,
+    pub out_of_range: OutOfRange,
+}
+
+impl ParseOptions {
+    pub fn strict() -> ParseOptions {
+        ParseOptions {
+            mode: ParseMode::Strict,
+            out_of_range: OutOfRange::Clamp,
+        }
+    }
+
+    pub fn recover() -> ParseOptions {
+        ParseOptions {
+            mode: ParseMode::Recover,
+            out_of_range: OutOfRange::Clamp,
+        }
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions::strict()
+    }
+}
+
+/// A problem that was worked around while parsing in `ParseMode::Recover`.
+/// Entry indices refer to the directory as it is stored in the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// The file ends before the end of the directory. Only the entries that
+    /// are entirely within the file were read.
+    TruncatedDirectory {
+        expected_entries: usize,
+        salvaged_entries: usize,
+    },
+
+    /// The lump extended past the end of the lump data and was shortened
+    ClampedEntry {
+        index: usize,
+        id: EntryId,
+        start: i32,
+        length: i32,
+        new_length: usize,
+    },
+
+    /// The lump was outside of the lump data and the entry was removed
+    DroppedEntry {
+        index: usize,
+        id: EntryId,
+        start: i32,
+        length: i32,
+    },
+}
+
+impl std::fmt::Display for ParseWarning {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParseWarning::TruncatedDirectory {
+                expected_entries,
+                salvaged_entries,
+            } => write!(
+                fmt,
+                "Truncated directory: salvaged {} of {} entries",
+                salvaged_entries, expected_entries
+            ),
+            ParseWarning::ClampedEntry {
+                index,
+                id,
+                start,
+                length,
+                new_length,
+            } => write!(
+                fmt,
+                "Entry {} ({}): start {}, length {} clamped to length {}",
+                index, id, start, length, new_length
+            ),
+            ParseWarning::DroppedEntry {
+                index,
+                id,
+                start,
+                length,
+            } => write!(
+                fmt,
+                "Entry {} ({}): start {}, length {} dropped",
+                index, id, start, length
+            ),
+        }
+    }
+}
+
+/// Like `parse_wad`, but with a choice of how to deal with damaged files.
+/// In `ParseMode::Recover`, the returned warnings list everything that was
+/// fixed. They are always empty in `ParseMode::Strict`.
+pub fn parse_wad_with_options(
+    data: Vec<u8>,
+    options: &ParseOptions,
+) -> Result<(Wad, Vec<ParseWarning>), Error> {
+    match options.mode {
+        ParseMode::Strict => Ok((parse_wad(data)?, vec![])),
+        ParseMode::Recover => recover(data, options.out_of_range),
+    }
+}
+
+pub fn load_wad_file_with_options(
+    filename: impl AsRef<Path>,
+    options: &ParseOptions,
+) -> Result<(Wad, Vec<ParseWarning>), LoadError> {
+    let filename = filename.as_ref();
+    let load = || -> Result<(Wad, Vec<ParseWarning>), LoadError> {
+        let data = std::fs::read(filename)?;
+        Ok(parse_wad_with_options(data, options)?)
+    };
+    load().map_err(|e| e.with_path(filename))
+}
+
+fn recover(mut data: Vec<u8>, out_of_range: OutOfRange) -> Result<(Wad, Vec<ParseWarning>), Error> {
+    let header = read_header(&data)?;
+    let mut warnings = vec![];
+
+    let available_entries =
+        data.len().saturating_sub(header.directory_offset) / DIRECTORY_ENTRY_BYTE_SIZE;
+    let n_entries = header.n_entries.min(available_entries);
+    if n_entries < header.n_entries {
+        warnings.push(ParseWarning::TruncatedDirectory {
+            expected_entries: header.n_entries,
+            salvaged_entries: n_entries,
+        });
+    }
+
+    // The lump data can't end inside the header, even if the directory
+    // offset says so
+    let data_end = header
+        .directory_offset
+        .min(data.len())
+        .max(HEADER_BYTE_SIZE);
+    let mut directory = Vec::with_capacity(n_entries * DIRECTORY_ENTRY_BYTE_SIZE);
+
+    for index in 0..n_entries {
+        let offset = header.directory_offset + index * DIRECTORY_ENTRY_BYTE_SIZE;
+        let mut raw_entry: RawEntry = [0; DIRECTORY_ENTRY_BYTE_SIZE];
+        raw_entry.copy_from_slice(&data[offset..offset + DIRECTORY_ENTRY_BYTE_SIZE]);
+
+        if lump_range(&raw_entry, data_end, data.len()).is_err() {
+            let id = WadSlice::entry_id_from_raw_entry(&raw_entry);
+            let start = LittleEndian::read_i32(&raw_entry[0..4]);
+            let length = LittleEndian::read_i32(&raw_entry[4..8]);
+
+            let clampable =
+                length > 0 && start >= HEADER_BYTE_SIZE as i32 && (start as usize) < data_end;
+
+            if out_of_range == OutOfRange::Clamp && clampable {
+                let new_length = data_end - start as usize;
+                LittleEndian::write_i32(&mut raw_entry[4..8], new_length as i32);
+                warnings.push(ParseWarning::ClampedEntry {
+                    index,
+                    id,
+                    start,
+                    length,
+                    new_length,
+                });
+            } else {
+                warnings.push(ParseWarning::DroppedEntry {
+                    index,
+                    id,
+                    start,
+                    length,
+                });
+                continue;
+            }
+        }
+
+        directory.extend_from_slice(&raw_entry);
+    }
+
+    // Rebuild the file with the salvaged directory directly after the lump
+    // data, so the result is a valid WAD file
+    let n_entries = directory.len() / DIRECTORY_ENTRY_BYTE_SIZE;
+    data.truncate(data_end);
+    data.extend_from_slice(&directory);
+    LittleEndian::write_i32(&mut data[4..8], n_entries as i32);
+    LittleEndian::write_i32(&mut data[8..12], data_end as i32);
+
+    let file_length = data.len();
+    let header = parse_header(&data, file_length)?;
+    Ok((Wad::from_owned(data, header, file_length), warnings))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    fn sample() -> Vec<u8> {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        builder.push(b"A", &b"aaaa"[..]);
+        builder.push(b"MARKER", vec![]);
+        builder.push(b"B", &b"bbbb"[..]);
+        builder.push(b"C", &b"cccc"[..]);
+        builder.to_bytes().unwrap()
+    }
+
+    fn set_lump_length(data: &mut [u8], index: usize, length: i32) {
+        let offset = LittleEndian::read_i32(&data[8..12]) as usize + index * 16 + 4;
+        LittleEndian::write_i32(&mut data[offset..offset + 4], length);
+    }
+
+    fn names(wad: &Wad) -> Vec<String> {
+        wad.id_iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn strict_is_like_parse_wad() {
+        let mut data = sample();
+        data.truncate(data.len() - 1);
+        assert!(matches!(
+            parse_wad_with_options(data, &ParseOptions::default()),
+            Err(Error::InvalidLength { .. })
+        ));
+
+        let (wad, warnings) = parse_wad_with_options(sample(), &ParseOptions::strict()).unwrap();
+        assert_eq!(wad.len(), 4);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn salvages_truncated_directory() {
+        let mut data = sample();
+        data.truncate(data.len() - 20);
+
+        let (wad, warnings) = parse_wad_with_options(data, &ParseOptions::recover()).unwrap();
+        assert_eq!(names(&wad), ["A", "MARKER"]);
+        assert_eq!(wad.lump(0).unwrap(), b"aaaa");
+        assert_eq!(
+            warnings,
+            [ParseWarning::TruncatedDirectory {
+                expected_entries: 4,
+                salvaged_entries: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn clamps_out_of_range_lumps() {
+        let mut data = sample();
+        set_lump_length(&mut data, 3, 100);
+
+        let (wad, warnings) = parse_wad_with_options(data, &ParseOptions::recover()).unwrap();
+        assert_eq!(wad.len(), 4);
+        assert_eq!(wad.lump(3).unwrap(), b"cccc");
+        assert!(matches!(
+            warnings[..],
+            [ParseWarning::ClampedEntry {
+                index: 3,
+                length: 100,
+                new_length: 4,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn drops_out_of_range_lumps() {
+        let mut data = sample();
+        set_lump_length(&mut data, 0, -1);
+        set_lump_length(&mut data, 2, 100);
+
+        let options = ParseOptions {
+            out_of_range: OutOfRange::Drop,
+            ..ParseOptions::recover()
+        };
+        let (wad, warnings) = parse_wad_with_options(data, &options).unwrap();
+        assert_eq!(names(&wad), ["MARKER", "C"]);
+        assert_eq!(wad.lump(1).unwrap(), b"cccc");
+        assert_eq!(warnings.len(), 2);
+        assert!(matches!(
+            warnings[1],
+            ParseWarning::DroppedEntry { index: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn recovered_wad_is_valid() {
+        let mut data = sample();
+        data.truncate(data.len() - 20);
+        let (wad, _) = parse_wad_with_options(data, &ParseOptions::recover()).unwrap();
+        let bytes = wad.into_editable().unwrap().to_bytes().unwrap();
+        assert_eq!(names(&parse_wad(bytes).unwrap()), ["A", "MARKER"]);
+    }
+
+    #[test]
+    fn directory_offset_inside_header() {
+        for directory_offset in 0..HEADER_BYTE_SIZE as i32 {
+            for n_entries in 0..2 {
+                let mut data = vec![0; HEADER_BYTE_SIZE + 16];
+                data[0..4].copy_from_slice(b"PWAD");
+                LittleEndian::write_i32(&mut data[4..8], n_entries);
+                LittleEndian::write_i32(&mut data[8..12], directory_offset);
+
+                let (wad, _) = parse_wad_with_options(data, &ParseOptions::recover()).unwrap();
+                assert!(wad.len() <= n_entries as usize);
+            }
+        }
+    }
+}
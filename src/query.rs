@@ -0,0 +1,416 @@
+use crate::entry::Entry;
+use crate::entry_id::{EntryId, EntryIdError};
+use crate::error::Error;
+use crate::map::MapRef;
+use crate::wad_slice::WadSlice;
+
+/// Selects one entry in a directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    /// An entry by name. `occurrence` picks among entries with the same
+    /// name: `None` or `Some(0)` is the first, `Some(1)` the second and so
+    /// on, while negative numbers count from the end, so `Some(-1)` is the
+    /// last.
+    Name {
+        id: EntryId,
+        occurrence: Option<isize>,
+    },
+
+    /// An entry by index. Negative numbers count from the end.
+    Index(isize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Continue the search after the selected entry
+    After(Selector),
+
+    /// Continue the search within the namespace with the given name. See
+    /// `WadSlice::namespace`.
+    Namespace(String),
+
+    /// Continue the search within the lumps of the selected map. A name
+    /// selects among maps with that name, and an index must be that of a
+    /// map header.
+    Map(Selector),
+}
+
+/// A query for locating a lump, as parsed from a string.
+///
+/// The simplest query is just the lump name, for example `endoom`. Matching
+/// is case insensitive.
+///
+///  * `name#n` selects the n-th lump with the given name, counting from 0.
+///    Negative numbers count from the end, so `playpal#-1` is the last
+///    `PLAYPAL`, which is the one the engine would use.
+///  * `#n` selects the lump at index n, and `#-1` is the last lump.
+///  * `a+b` locates `b` after `a`, for example `e1m3+linedefs`.
+///  * `ns/b` locates `b` within the section delimited by `ns_START` and
+///    `ns_END`, for example `f/step1`.
+///  * `map:b` locates `b` among the lumps of the given map, for example
+///    `e1m3:linedefs`. Unlike `+`, this never finds a lump belonging to the
+///    next map. `#` picks among maps with the same name, so `map01#-1:things`
+///    is in the last `MAP01`.
+///
+/// The operators can be chained, for example `p/p1/wall00_1` or
+/// `f/e1m3:things`.
+///
+/// Names may contain the escapes of `EntryId::escaped`, for example
+/// `e1m1\x80`, or `a\x2Bb` for a lump named `A+B`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub steps: Vec<Step>,
+    pub target: Selector,
+}
+
+#[derive(Debug)]
+pub enum QueryError {
+    InvalidLumpId(String, EntryIdError),
+    InvalidIndex(String),
+    LumpNotFound(String),
+    NamespaceNotFound(String),
+    MapNotFound(String),
+    IndexOutOfBounds(isize),
+    Entry(Error),
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            QueryError::InvalidLumpId(x, reason) => {
+                write!(fmt, "Invalid lump ID: {:?}: {}", x, reason)
+            }
+            QueryError::InvalidIndex(x) => write!(fmt, "Invalid index: {:?}", x),
+            QueryError::LumpNotFound(x) => write!(fmt, "Lump not found: {:?}", x),
+            QueryError::NamespaceNotFound(x) => write!(fmt, "Namespace not found: {:?}", x),
+            QueryError::MapNotFound(x) => write!(fmt, "Map not found: {:?}", x),
+            QueryError::IndexOutOfBounds(x) => write!(fmt, "Index out of bounds: {}", x),
+            QueryError::Entry(e) => write!(fmt, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            QueryError::Entry(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+fn parse_index(s: &str) -> Result<isize, QueryError> {
+    s.parse()
+        .map_err(|_| QueryError::InvalidIndex(s.to_string()))
+}
+
+/// Names are normalized, since matching is case insensitive anyway. A
+/// backslash that does not start `\\` or `\xNN` is taken literally, so that
+/// names like `VILE\1` can be written as is.
+fn parse_id(s: &str) -> Result<EntryId, QueryError> {
+    let id = match EntryId::from_escaped(s) {
+        Err(EntryIdError::InvalidEscape(escape)) if !escape.starts_with("\\x") => {
+            EntryId::from_str(s).ok_or(EntryIdError::InvalidEscape(escape))
+        }
+        id => id,
+    };
+    id.map(|id| id.normalized())
+        .map_err(|reason| QueryError::InvalidLumpId(s.to_string(), reason))
+}
+
+fn parse_selector(s: &str) -> Result<Selector, QueryError> {
+    match s.find('#') {
+        Some(0) => Ok(Selector::Index(parse_index(&s[1..])?)),
+        Some(hash) => Ok(Selector::Name {
+            id: parse_id(&s[..hash])?,
+            occurrence: Some(parse_index(&s[hash + 1..])?),
+        }),
+        None => Ok(Selector::Name {
+            id: parse_id(s)?,
+            occurrence: None,
+        }),
+    }
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Query, QueryError> {
+        let mut steps = vec![];
+        let mut last = 0;
+        for (index, op) in query.match_indices(['+', '/', ':']) {
+            let part = &query[last..index];
+            last = index + op.len();
+
+            steps.push(match op {
+                "+" => Step::After(parse_selector(part)?),
+                "/" => Step::Namespace(part.to_string()),
+                ":" => Step::Map(parse_selector(part)?),
+                _ => unreachable!(),
+            });
+        }
+
+        let target = parse_selector(&query[last..])?;
+
+        Ok(Query { steps, target })
+    }
+}
+
+impl std::str::FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Query, QueryError> {
+        Query::parse(s)
+    }
+}
+
+fn resolve_index(len: usize, index: isize) -> Option<usize> {
+    if index < 0 {
+        len.checked_sub(index.unsigned_abs())
+    } else if (index as usize) < len {
+        Some(index as usize)
+    } else {
+        None
+    }
+}
+
+/// Find the index of the selected entry in `wad`
+fn select(wad: &WadSlice, selector: &Selector) -> Result<usize, QueryError> {
+    match *selector {
+        Selector::Index(index) => {
+            resolve_index(wad.len(), index).ok_or(QueryError::IndexOutOfBounds(index))
+        }
+        Selector::Name { id, occurrence } => {
+            let not_found = || QueryError::LumpNotFound(id.to_string());
+            match occurrence {
+                None => wad.index_of(id).ok_or_else(not_found),
+                Some(occurrence) => {
+                    let indices: Vec<_> = wad.all_indices_of(id).collect();
+                    let index = resolve_index(indices.len(), occurrence).ok_or_else(not_found)?;
+                    Ok(indices[index])
+                }
+            }
+        }
+    }
+}
+
+/// Find the selected map in `wad`
+fn select_map<'a>(wad: &WadSlice<'a>, selector: &Selector) -> Result<MapRef<'a>, QueryError> {
+    let maps = wad.maps();
+    match *selector {
+        Selector::Index(index) => {
+            let index =
+                resolve_index(wad.len(), index).ok_or(QueryError::IndexOutOfBounds(index))?;
+            maps.into_iter()
+                .find(|map| map.index == index)
+                .ok_or_else(|| QueryError::MapNotFound(format!("#{}", index)))
+        }
+        Selector::Name { id, occurrence } => {
+            let mut maps: Vec<_> = maps
+                .into_iter()
+                .filter(|map| map.name.matches(&id))
+                .collect();
+            let index = resolve_index(maps.len(), occurrence.unwrap_or(0))
+                .ok_or_else(|| QueryError::MapNotFound(id.to_string()))?;
+            Ok(maps.swap_remove(index))
+        }
+    }
+}
+
+pub(crate) fn run_query<'a>(wad: &WadSlice<'a>, query: &Query) -> Result<Entry<'a>, QueryError> {
+    let mut wad = *wad;
+
+    for step in &query.steps {
+        wad = match step {
+            Step::After(selector) => {
+                let index = select(&wad, selector)?;
+                wad.slice(index + 1..)
+            }
+            Step::Namespace(name) => {
+                wad.namespace(name)
+                    .ok_or_else(|| QueryError::NamespaceNotFound(name.clone()))?
+                    .entries
+            }
+            Step::Map(selector) => select_map(&wad, selector)?.lumps,
+        };
+    }
+
+    let index = select(&wad, &query.target)?;
+    wad.entry(index).map_err(QueryError::Entry)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    fn sample() -> Wad {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        for (name, lump) in &[
+            ("PLAYPAL", "pal1"),
+            ("E1M1", ""),
+            ("THINGS", "t1"),
+            ("E1M2", ""),
+            ("THINGS", "t2"),
+            ("LINEDEFS", "l2"),
+            ("E1M3", ""),
+            ("THINGS", "t3"),
+            ("FF_START", ""),
+            ("LINEDEFS", "flat"),
+            ("FF_END", ""),
+            ("PLAYPAL", "pal2"),
+        ] {
+            builder.push(EntryId::from_str(name).unwrap(), lump.as_bytes());
+        }
+        parse_wad(builder.to_bytes().unwrap()).unwrap()
+    }
+
+    fn query(wad: &Wad, query: &str) -> Result<String, QueryError> {
+        let entry = wad.as_slice().query(&query.parse()?)?;
+        Ok(String::from_utf8(entry.lump.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn parses_steps() {
+        assert_eq!(
+            Query::parse("f/e1m3:things#-1").unwrap(),
+            Query {
+                steps: vec![
+                    Step::Namespace("f".to_string()),
+                    Step::Map(Selector::Name {
+                        id: EntryId::from_str("E1M3").unwrap(),
+                        occurrence: None
+                    })
+                ],
+                target: Selector::Name {
+                    id: EntryId::from_str("THINGS").unwrap(),
+                    occurrence: Some(-1)
+                },
+            }
+        );
+        assert_eq!(
+            Query::parse("#5+x").unwrap().steps,
+            [Step::After(Selector::Index(5))]
+        );
+    }
+
+    #[test]
+    fn parses_escaped_names() {
+        assert_eq!(
+            Query::parse("e1m1\\x80#1").unwrap().target,
+            Selector::Name {
+                id: EntryId::from_bytes(b"E1M1\x80\0\0\0"),
+                occurrence: Some(1),
+            }
+        );
+
+        let mut builder = WadBuilder::new(Kind::PWad);
+        builder.push(b"VILE\\1", b"vile".to_vec());
+        let wad = parse_wad(builder.to_bytes().unwrap()).unwrap();
+        assert_eq!(query(&wad, "vile\\1").unwrap(), "vile");
+        assert_eq!(query(&wad, "vile\\\\1").unwrap(), "vile");
+
+        assert!(matches!(
+            Query::parse("a\\x2"),
+            Err(QueryError::InvalidLumpId(_, EntryIdError::InvalidEscape(_)))
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_queries() {
+        assert!(matches!(
+            Query::parse("toolongname"),
+            Err(QueryError::InvalidLumpId(..))
+        ));
+        assert!(matches!(
+            Query::parse("#x"),
+            Err(QueryError::InvalidIndex(_))
+        ));
+        assert!(matches!(
+            Query::parse("a+"),
+            Err(QueryError::InvalidLumpId(..))
+        ));
+    }
+
+    #[test]
+    fn resolves_names_and_occurrences() {
+        let wad = sample();
+        assert_eq!(query(&wad, "playpal").unwrap(), "pal1");
+        assert_eq!(query(&wad, "playpal#1").unwrap(), "pal2");
+        assert_eq!(query(&wad, "playpal#-1").unwrap(), "pal2");
+        assert!(matches!(
+            query(&wad, "playpal#2"),
+            Err(QueryError::LumpNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn resolves_indices() {
+        let wad = sample();
+        assert_eq!(query(&wad, "#2").unwrap(), "t1");
+        assert_eq!(query(&wad, "#-1").unwrap(), "pal2");
+        assert_eq!(query(&wad, "e1m2+#0").unwrap(), "t2");
+        assert!(matches!(
+            query(&wad, "#12"),
+            Err(QueryError::IndexOutOfBounds(12))
+        ));
+    }
+
+    #[test]
+    fn resolves_after_and_namespace() {
+        let wad = sample();
+        assert_eq!(query(&wad, "e1m2+things").unwrap(), "t2");
+        assert_eq!(query(&wad, "f/linedefs").unwrap(), "flat");
+        assert!(matches!(
+            query(&wad, "s/linedefs"),
+            Err(QueryError::NamespaceNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn map_step_stays_within_map() {
+        let wad = sample();
+        // + finds the next map's lump, which is what : prevents
+        assert_eq!(query(&wad, "e1m1+linedefs").unwrap(), "l2");
+        assert!(matches!(
+            query(&wad, "e1m1:linedefs"),
+            Err(QueryError::LumpNotFound(_))
+        ));
+        assert_eq!(query(&wad, "e1m2:linedefs").unwrap(), "l2");
+        assert!(matches!(
+            query(&wad, "e1m4:things"),
+            Err(QueryError::MapNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn map_step_with_selector() {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        for (name, lump) in &[
+            ("MAP01", ""),
+            ("THINGS", "t1"),
+            ("MAP01", ""),
+            ("THINGS", "t2"),
+        ] {
+            builder.push(EntryId::from_str(name).unwrap(), lump.as_bytes());
+        }
+        let wad = parse_wad(builder.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(
+            Query::parse("map01#-1:things").unwrap().steps,
+            [Step::Map(Selector::Name {
+                id: EntryId::from_str("MAP01").unwrap(),
+                occurrence: Some(-1)
+            })]
+        );
+        assert_eq!(query(&wad, "map01:things").unwrap(), "t1");
+        assert_eq!(query(&wad, "map01#-1:things").unwrap(), "t2");
+        assert_eq!(query(&wad, "#2:things").unwrap(), "t2");
+        assert!(matches!(
+            query(&wad, "#1:things"),
+            Err(QueryError::MapNotFound(_))
+        ));
+        assert!(matches!(
+            query(&wad, "map01#2:things"),
+            Err(QueryError::MapNotFound(_))
+        ));
+    }
+}
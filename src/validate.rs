@@ -0,0 +1,596 @@
+use std::collections::HashMap;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::entry_id::EntryId;
+use crate::map::*;
+use crate::namespace::Namespace;
+use crate::wad::*;
+use crate::wad_slice::WadSlice;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Harmless, but possibly unintended
+    Info,
+
+    /// Likely to cause problems with some engines or tools
+    Warning,
+
+    /// The WAD file is broken
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FindingKind {
+    /// The entry has a negative start or length
+    InvalidEntry {
+        index: usize,
+        id: EntryId,
+    },
+
+    /// The lump starts within the header
+    LumpInHeader {
+        index: usize,
+        id: EntryId,
+    },
+
+    /// The lump overlaps the directory
+    LumpInDirectory {
+        index: usize,
+        id: EntryId,
+    },
+
+    /// The lump extends past the end of the file
+    LumpPastEnd {
+        index: usize,
+        id: EntryId,
+    },
+
+    /// The lump lies entirely after the directory. The Doom engine can read
+    /// it, but this library only reads lump data before the directory.
+    LumpAfterDirectory {
+        index: usize,
+        id: EntryId,
+    },
+
+    /// The lumps of two entries partially overlap. Entries that share the
+    /// exact same lump data are not reported.
+    OverlappingLumps {
+        first: usize,
+        second: usize,
+    },
+
+    /// A range of the lump data that is not part of any lump
+    UnusedGap {
+        start: usize,
+        length: usize,
+    },
+
+    /// Two entries directly within the same namespace have the same name,
+    /// so only one of them can be found by the engine
+    DuplicateName {
+        namespace: String,
+        id: EntryId,
+        first: usize,
+        second: usize,
+    },
+
+    /// A `_START` marker without a matching `_END` marker
+    UnterminatedNamespace {
+        namespace: String,
+        start: usize,
+    },
+
+    NonAsciiName {
+        index: usize,
+    },
+
+    /// Lump lookups in the engine are case insensitive, but some tools are
+    /// not
+    LowercaseName {
+        index: usize,
+        id: EntryId,
+    },
+
+    /// A map lump that comes before a lump it usually follows, for example
+    /// `LINEDEFS` before `THINGS`
+    MapLumpOrder {
+        map: EntryId,
+        index: usize,
+        id: EntryId,
+    },
+}
+
+impl FindingKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            FindingKind::InvalidEntry { .. }
+            | FindingKind::LumpInHeader { .. }
+            | FindingKind::LumpInDirectory { .. }
+            | FindingKind::LumpPastEnd { .. }
+            | FindingKind::LumpAfterDirectory { .. } => Severity::Error,
+            FindingKind::OverlappingLumps { .. }
+            | FindingKind::DuplicateName { .. }
+            | FindingKind::UnterminatedNamespace { .. }
+            | FindingKind::NonAsciiName { .. }
+            | FindingKind::MapLumpOrder { .. } => Severity::Warning,
+            FindingKind::UnusedGap { .. } | FindingKind::LowercaseName { .. } => Severity::Info,
+        }
+    }
+}
+
+impl std::fmt::Display for FindingKind {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FindingKind::InvalidEntry { index, id } => write!(
+                fmt,
+                "Entry {} ({}) has a negative start or length",
+                index, id
+            ),
+            FindingKind::LumpInHeader { index, id } => {
+                write!(fmt, "Entry {} ({}) starts within the header", index, id)
+            }
+            FindingKind::LumpInDirectory { index, id } => {
+                write!(fmt, "Entry {} ({}) overlaps the directory", index, id)
+            }
+            FindingKind::LumpPastEnd { index, id } => write!(
+                fmt,
+                "Entry {} ({}) extends past the end of the file",
+                index, id
+            ),
+            FindingKind::LumpAfterDirectory { index, id } => {
+                write!(fmt, "Entry {} ({}) lies after the directory", index, id)
+            }
+            FindingKind::OverlappingLumps { first, second } => write!(
+                fmt,
+                "Entries {} and {} have overlapping lumps",
+                first, second
+            ),
+            FindingKind::UnusedGap { start, length } => {
+                write!(fmt, "{} unused bytes at offset {}", length, start)
+            }
+            FindingKind::DuplicateName {
+                namespace,
+                id,
+                first,
+                second,
+            } => write!(
+                fmt,
+                "Entries {} and {} are both named {} in namespace {}",
+                first, second, id, namespace
+            ),
+            FindingKind::UnterminatedNamespace { namespace, start } => write!(
+                fmt,
+                "Namespace {} starting at entry {} is not terminated",
+                namespace, start
+            ),
+            FindingKind::NonAsciiName { index } => {
+                write!(fmt, "Entry {} has a non-ASCII name", index)
+            }
+            FindingKind::LowercaseName { index, id } => {
+                write!(fmt, "Entry {} ({}) has a lowercase name", index, id)
+            }
+            FindingKind::MapLumpOrder { map, index, id } => write!(
+                fmt,
+                "Entry {} ({}) in map {} is out of order",
+                index, id, map
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub kind: FindingKind,
+}
+
+impl From<FindingKind> for Finding {
+    fn from(kind: FindingKind) -> Finding {
+        Finding {
+            severity: kind.severity(),
+            kind,
+        }
+    }
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}: {}", self.severity, self.kind)
+    }
+}
+
+impl Wad {
+    /// Check the WAD file for structural problems. An empty result means
+    /// that nothing was found.
+    pub fn validate(&self) -> Vec<Finding> {
+        let wad = self.as_slice();
+        let mut findings = vec![];
+
+        check_lumps(&wad, &mut findings);
+        check_names(&wad, &mut findings);
+        check_namespaces(&wad.namespaces(), &mut findings);
+        check_maps(&wad, &mut findings);
+
+        findings.into_iter().map(Finding::from).collect()
+    }
+}
+
+fn check_lumps(wad: &WadSlice, findings: &mut Vec<FindingKind>) {
+    let data_end = wad.data().len();
+    let directory_end = data_end + wad.len() * DIRECTORY_ENTRY_BYTE_SIZE;
+
+    // (start, end, index) of every valid, non-empty lump
+    let mut lumps = vec![];
+
+    for (index, raw_entry) in wad.raw_directory().iter().enumerate() {
+        let start = LittleEndian::read_i32(&raw_entry[0..4]);
+        let length = LittleEndian::read_i32(&raw_entry[4..8]);
+
+        match wad.entry_from_raw_entry(raw_entry) {
+            Ok(entry) => {
+                if !entry.lump.is_empty() {
+                    let start = start as usize;
+                    lumps.push((start, start + entry.lump.len(), index));
+                }
+            }
+            Err(_) => {
+                let id = WadSlice::entry_id_from_raw_entry(raw_entry);
+                findings.push(if start < 0 || length < 0 {
+                    FindingKind::InvalidEntry { index, id }
+                } else if (start as usize) < HEADER_BYTE_SIZE {
+                    FindingKind::LumpInHeader { index, id }
+                } else if start as u64 + length as u64 > wad.file_length() as u64 {
+                    FindingKind::LumpPastEnd { index, id }
+                } else if (start as usize) < directory_end {
+                    FindingKind::LumpInDirectory { index, id }
+                } else {
+                    FindingKind::LumpAfterDirectory { index, id }
+                });
+            }
+        }
+    }
+
+    lumps.sort_unstable();
+
+    // Sweep through the lumps in order of their start, keeping track of
+    // the lumps that have not ended yet. Of lumps that share the exact same
+    // range, only the first is kept, so overlaps are reported once.
+    let mut covered_until = HEADER_BYTE_SIZE;
+    let mut active: Vec<(usize, usize, usize)> = vec![];
+
+    for &(start, end, index) in &lumps {
+        if start > covered_until {
+            findings.push(FindingKind::UnusedGap {
+                start: covered_until,
+                length: start - covered_until,
+            });
+        }
+        covered_until = covered_until.max(end);
+
+        active.retain(|&(_, other_end, _)| other_end > start);
+        if active.iter().any(|&(s, e, _)| (s, e) == (start, end)) {
+            continue;
+        }
+        for &(_, _, other_index) in &active {
+            findings.push(FindingKind::OverlappingLumps {
+                first: other_index.min(index),
+                second: other_index.max(index),
+            });
+        }
+        active.push((start, end, index));
+    }
+
+    if data_end > covered_until {
+        findings.push(FindingKind::UnusedGap {
+            start: covered_until,
+            length: data_end - covered_until,
+        });
+    }
+}
+
+fn check_names(wad: &WadSlice, findings: &mut Vec<FindingKind>) {
+    for (index, id) in wad.id_iter().enumerate() {
+        let name = id.as_bytes().iter().take_while(|&&x| x != 0);
+
+        if !name.clone().all(u8::is_ascii) {
+            findings.push(FindingKind::NonAsciiName { index });
+        } else if name.clone().any(u8::is_ascii_lowercase) {
+            findings.push(FindingKind::LowercaseName { index, id });
+        }
+    }
+}
+
+fn check_namespaces(namespaces: &[Namespace], findings: &mut Vec<FindingKind>) {
+    for namespace in namespaces {
+        if !namespace.is_terminated() {
+            findings.push(FindingKind::UnterminatedNamespace {
+                namespace: namespace.name.clone(),
+                start: namespace.start,
+            });
+        }
+
+        // Only entries directly in this namespace count. Nested namespaces
+        // and their markers are checked separately.
+        let nested = |index: usize| {
+            namespace.children.iter().any(|child| {
+                let child_end = child.end.unwrap_or(child.start + child.entries.len());
+                (child.start..=child_end).contains(&index)
+            })
+        };
+
+        let mut seen = HashMap::new();
+        for (i, id) in namespace.entries.id_iter().enumerate() {
+            let index = namespace.entries_start() + i;
+            if nested(index) {
+                continue;
+            }
+
+            let id = id.normalized();
+            if let Some(&first) = seen.get(&id) {
+                findings.push(FindingKind::DuplicateName {
+                    namespace: namespace.name.clone(),
+                    id,
+                    first,
+                    second: index,
+                });
+            } else {
+                seen.insert(id, index);
+            }
+        }
+
+        check_namespaces(&namespace.children, findings);
+    }
+}
+
+fn check_maps(wad: &WadSlice, findings: &mut Vec<FindingKind>) {
+    for map in wad.maps() {
+        if map.format == MapFormat::Udmf {
+            continue;
+        }
+
+        let mut last = 0;
+        for (i, id) in map.lumps.id_iter().enumerate() {
+            let position = BINARY_MAP_LUMPS
+                .iter()
+                .position(|name| is(id, name))
+                .expect("binary maps consist of known lumps");
+
+            if position < last {
+                findings.push(FindingKind::MapLumpOrder {
+                    map: map.name,
+                    index: map.index + 1 + i,
+                    id,
+                });
+            }
+            last = last.max(position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    fn wad(lumps: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        for &(name, lump) in lumps {
+            builder.push(EntryId::from_bytes(&pad(name)), lump);
+        }
+        builder.to_bytes().unwrap()
+    }
+
+    fn pad(name: &str) -> [u8; 8] {
+        let mut buf = [0; 8];
+        buf[..name.len()].copy_from_slice(name.as_bytes());
+        buf
+    }
+
+    fn set_lump(data: &mut [u8], index: usize, start: i32, length: i32) {
+        let offset = LittleEndian::read_i32(&data[8..12]) as usize + index * 16;
+        LittleEndian::write_i32(&mut data[offset..offset + 4], start);
+        LittleEndian::write_i32(&mut data[offset + 4..offset + 8], length);
+    }
+
+    fn kinds(data: Vec<u8>) -> Vec<FindingKind> {
+        parse_wad(data)
+            .unwrap()
+            .validate()
+            .into_iter()
+            .map(|finding| finding.kind)
+            .collect()
+    }
+
+    #[test]
+    fn valid_wad_has_no_findings() {
+        let data = wad(&[
+            ("PLAYPAL", b"pal"),
+            ("E1M1", b""),
+            ("THINGS", b"t"),
+            ("LINEDEFS", b"l"),
+            ("S_START", b""),
+            ("TROOA1", b"troo"),
+            ("S_END", b""),
+        ]);
+        assert_eq!(kinds(data), []);
+    }
+
+    #[test]
+    fn finds_bad_lump_positions() {
+        let mut data = wad(&[("A", b"aaaa"), ("B", b"bbbb"), ("C", b"cccc"), ("D", b"dd")]);
+        set_lump(&mut data, 0, 4, 4);
+        set_lump(&mut data, 1, 24, 4);
+        set_lump(&mut data, 2, 1000, 4);
+        set_lump(&mut data, 3, -1, 2);
+
+        let a = EntryId::from_str("A").unwrap();
+        let b = EntryId::from_str("B").unwrap();
+        let c = EntryId::from_str("C").unwrap();
+        let d = EntryId::from_str("D").unwrap();
+        assert_eq!(
+            kinds(data),
+            [
+                FindingKind::LumpInHeader { index: 0, id: a },
+                FindingKind::LumpInDirectory { index: 1, id: b },
+                FindingKind::LumpPastEnd { index: 2, id: c },
+                FindingKind::InvalidEntry { index: 3, id: d },
+                FindingKind::UnusedGap {
+                    start: 12,
+                    length: 14
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn lump_running_past_the_end_is_not_in_the_directory() {
+        let mut data = wad(&[("A", b"aaaa")]);
+        set_lump(&mut data, 0, 12, 1000);
+
+        let a = EntryId::from_str("A").unwrap();
+        assert_eq!(
+            kinds(data),
+            [
+                FindingKind::LumpPastEnd { index: 0, id: a },
+                FindingKind::UnusedGap {
+                    start: 12,
+                    length: 4
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn lump_after_the_directory_is_not_past_the_end() {
+        let mut data = wad(&[("A", b"aaaa")]);
+        let file_length = data.len() as i32;
+        data.extend_from_slice(b"trailing");
+        set_lump(&mut data, 0, file_length, 8);
+
+        let a = EntryId::from_str("A").unwrap();
+        assert_eq!(
+            kinds(data),
+            [
+                FindingKind::LumpAfterDirectory { index: 0, id: a },
+                FindingKind::UnusedGap {
+                    start: 12,
+                    length: 4
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_overlaps_and_gaps() {
+        let mut data = wad(&[("A", b"aaaa"), ("B", b"bbbb"), ("C", b"cccc")]);
+        // B overlaps the second half of A, C is a copy of A
+        set_lump(&mut data, 1, 14, 4);
+        set_lump(&mut data, 2, 12, 4);
+
+        assert_eq!(
+            kinds(data),
+            [
+                FindingKind::OverlappingLumps {
+                    first: 0,
+                    second: 1
+                },
+                FindingKind::UnusedGap {
+                    start: 18,
+                    length: 6
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_nested_and_chained_overlaps() {
+        let mut data = wad(&[("A", &[0; 100][..]), ("B", b""), ("C", b""), ("D", b"")]);
+        // B and C are within A and overlap each other, D overlaps only C
+        set_lump(&mut data, 0, 12, 100);
+        set_lump(&mut data, 1, 22, 10);
+        set_lump(&mut data, 2, 27, 15);
+        set_lump(&mut data, 3, 40, 10);
+
+        let overlaps: Vec<_> = kinds(data)
+            .into_iter()
+            .filter_map(|kind| match kind {
+                FindingKind::OverlappingLumps { first, second } => Some((first, second)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(overlaps, [(0, 1), (0, 2), (1, 2), (0, 3), (2, 3)]);
+    }
+
+    #[test]
+    fn finds_namespace_problems() {
+        let data = wad(&[
+            ("F_START", b""),
+            ("FLOOR0_1", b""),
+            ("F1_START", b""),
+            ("FLOOR0_1", b""),
+            ("F1_END", b""),
+            ("floor0_1", b""),
+            ("F_END", b""),
+            ("S_START", b""),
+        ]);
+        let floor = EntryId::from_str("FLOOR0_1").unwrap();
+        assert_eq!(
+            kinds(data),
+            [
+                FindingKind::LowercaseName {
+                    index: 5,
+                    id: EntryId::from_bytes(&pad("floor0_1"))
+                },
+                FindingKind::DuplicateName {
+                    namespace: "F".to_string(),
+                    id: floor,
+                    first: 1,
+                    second: 5
+                },
+                FindingKind::UnterminatedNamespace {
+                    namespace: "S".to_string(),
+                    start: 7
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_map_lump_order() {
+        let data = wad(&[
+            ("MAP01", b""),
+            ("THINGS", b""),
+            ("SIDEDEFS", b""),
+            ("LINEDEFS", b""),
+        ]);
+        assert_eq!(
+            kinds(data),
+            [FindingKind::MapLumpOrder {
+                map: EntryId::from_str("MAP01").unwrap(),
+                index: 3,
+                id: EntryId::from_str("LINEDEFS").unwrap(),
+            }]
+        );
+    }
+
+    #[test]
+    fn findings_have_severity() {
+        let mut data = wad(&[("A", b"aaaa")]);
+        set_lump(&mut data, 0, 1000, 4);
+        let findings = parse_wad(data).unwrap().validate();
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert_eq!(findings[1].severity, Severity::Info);
+        assert!(Severity::Error > Severity::Warning);
+    }
+}
@@ -1,3 +1,4 @@
+use std::ops::Range;
 use std::path::Path;
 use std::slice::SliceIndex;
 
@@ -5,24 +6,54 @@ use byteorder::{ByteOrder, LittleEndian};
 
 use crate::entry::Entry;
 use crate::entry_id::EntryId;
+use crate::entry_id_pattern::EntryIdPattern;
 use crate::error::{Error, LoadError};
 use crate::iterator::*;
+use crate::wad_index::WadIndex;
 use crate::wad_slice::WadSlice;
 
 pub(crate) const HEADER_BYTE_SIZE: usize = 12;
 pub(crate) const DIRECTORY_ENTRY_BYTE_SIZE: usize = 16;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Kind {
     IWad,
     PWad,
 }
 
+impl Kind {
+    pub(crate) fn magic(&self) -> &'static [u8; 4] {
+        match self {
+            Kind::IWad => b"IWAD",
+            Kind::PWad => b"PWAD",
+        }
+    }
+}
+
+enum Storage {
+    Owned(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl std::ops::Deref for Storage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Storage::Owned(data) => data,
+            #[cfg(feature = "mmap")]
+            Storage::Mapped(map) => map,
+        }
+    }
+}
+
 pub struct Wad {
     kind: Kind,
-    data: Vec<u8>,
+    data: Storage,
     directory_offset: usize,
     n_entries: usize,
+    file_length: usize,
 }
 
 pub type RawEntry = [u8; DIRECTORY_ENTRY_BYTE_SIZE];
@@ -36,24 +67,21 @@ impl Wad {
         self.n_entries
     }
 
-    fn directory(&self) -> &[RawEntry] {
-        let directory = &self.data[self.directory_offset..];
-
-        unsafe {
-            // This is safe because the bounds and size of the entry table were
-            // verified in parse_wad
+    pub fn is_empty(&self) -> bool {
+        self.n_entries == 0
+    }
 
-            std::slice::from_raw_parts(
-                std::mem::transmute(directory.as_ptr()),
-                directory.len() / DIRECTORY_ENTRY_BYTE_SIZE,
-            )
-        }
+    fn directory(&self) -> &[RawEntry] {
+        directory(&self.data, self.directory_offset, self.n_entries)
     }
 
     pub fn entry_id_from_raw_entry(raw_entry: &RawEntry) -> EntryId {
         WadSlice::entry_id_from_raw_entry(raw_entry)
     }
 
+    /// # Safety
+    ///
+    /// `index` must be less than `self.len()`.
     pub unsafe fn entry_id_unchecked(&self, index: usize) -> EntryId {
         self.as_slice().entry_id_unchecked(index)
     }
@@ -62,43 +90,101 @@ impl Wad {
         self.as_slice().entry_id(index)
     }
 
-    pub fn id_iter(&self) -> IdIterator {
+    pub fn id_iter(&self) -> IdIterator<'_> {
         IdIterator::new(self)
     }
 
+    /// Index of the first entry with the given ID
     pub fn index_of(&self, id: impl Into<EntryId>) -> Option<usize> {
         self.as_slice().index_of(id)
     }
 
-    pub fn entry_from_raw_entry(&self, raw_entry: &RawEntry) -> Result<Entry, Error> {
+    /// Index of the last entry with the given ID, as resolved by the Doom
+    /// engine
+    pub fn rindex_of(&self, id: impl Into<EntryId>) -> Option<usize> {
+        self.as_slice().rindex_of(id)
+    }
+
+    pub fn all_indices_of(&self, id: impl Into<EntryId>) -> impl Iterator<Item = usize> + '_ {
+        self.as_slice().all_indices_of(id)
+    }
+
+    pub fn find_all(
+        &self,
+        pattern: &EntryIdPattern,
+    ) -> impl Iterator<Item = Result<(usize, Entry<'_>), Error>> + '_ {
+        self.as_slice().find_all(pattern)
+    }
+
+    pub fn build_index(&self) -> WadIndex {
+        self.as_slice().build_index()
+    }
+
+    pub fn entry_from_raw_entry(&self, raw_entry: &RawEntry) -> Result<Entry<'_>, Error> {
         self.as_slice().entry_from_raw_entry(raw_entry)
     }
 
-    pub unsafe fn entry_unchecked(&self, index: usize) -> Result<Entry, Error> {
+    /// # Safety
+    ///
+    /// `index` must be less than `self.len()`.
+    pub unsafe fn entry_unchecked(&self, index: usize) -> Result<Entry<'_>, Error> {
         self.as_slice().entry_unchecked(index)
     }
 
-    pub fn entry(&self, index: usize) -> Result<Entry, Error> {
+    pub fn entry(&self, index: usize) -> Result<Entry<'_>, Error> {
         self.as_slice().entry(index)
     }
 
-    pub fn entry_iter(&self) -> EntryIterator {
+    pub fn lump(&self, index: usize) -> Result<&[u8], Error> {
+        self.as_slice().lump(index)
+    }
+
+    /// The location of the lump in the file. Zero-length lumps are placed
+    /// right after the header, whatever their directory entry says.
+    pub fn lump_range(&self, index: usize) -> Result<Range<usize>, Error> {
+        self.as_slice().lump_range(index)
+    }
+
+    /// Panics when reaching an invalid entry. See `try_entry_iter` for a
+    /// non-panicking alternative.
+    pub fn entry_iter(&self) -> EntryIterator<'_> {
         EntryIterator::new(self)
     }
 
+    pub fn try_entry_iter(&self) -> TryEntryIterator<'_> {
+        TryEntryIterator::new(self)
+    }
+
+    /// The lump of the first entry with the given ID
     pub fn by_id(&self, id: impl Into<EntryId>) -> Option<&[u8]> {
         self.as_slice().by_id(id)
     }
 
-    pub fn slice(&self, slice_index: impl SliceIndex<[RawEntry], Output = [RawEntry]>) -> WadSlice {
+    /// The lump of the last entry with the given ID, as resolved by the Doom
+    /// engine
+    pub fn by_id_last(&self, id: impl Into<EntryId>) -> Option<&[u8]> {
+        self.as_slice().by_id_last(id)
+    }
+
+    /// See `WadSlice::slice`
+    pub fn slice(
+        &self,
+        slice_index: impl SliceIndex<[RawEntry], Output = [RawEntry]>,
+    ) -> WadSlice<'_> {
         self.as_slice().slice(slice_index)
     }
 
-    pub fn as_slice(&self) -> WadSlice {
-        WadSlice::new(&self.data[0..self.directory_offset], self.directory())
+    pub fn as_slice(&self) -> WadSlice<'_> {
+        WadSlice::new(
+            &self.data[0..self.directory_offset],
+            self.directory(),
+            self.file_length,
+        )
     }
 }
 
+/// Panics if the index is out of bounds or the entry is invalid. See
+/// `Wad::lump` for a non-panicking alternative.
 impl std::ops::Index<usize> for Wad {
     type Output = [u8];
 
@@ -107,49 +193,265 @@ impl std::ops::Index<usize> for Wad {
     }
 }
 
-pub fn parse_wad(mut data: Vec<u8>) -> Result<Wad, Error> {
+pub(crate) struct Header {
+    pub kind: Kind,
+    pub n_entries: usize,
+    pub directory_offset: usize,
+}
+
+impl Header {
+    pub fn directory_end(&self) -> usize {
+        // Overflow is ruled out by read_header
+        self.directory_offset + self.n_entries * DIRECTORY_ENTRY_BYTE_SIZE
+    }
+}
+
+/// Parse the header without verifying that the directory it describes is
+/// present. Only the first `HEADER_BYTE_SIZE` bytes of `data` are inspected.
+pub(crate) fn read_header(data: &[u8]) -> Result<Header, Error> {
     if data.len() < HEADER_BYTE_SIZE {
-        return Err(Error::InvalidLength);
+        return Err(Error::InvalidLength {
+            expected: HEADER_BYTE_SIZE,
+            actual: data.len(),
+        });
     }
 
-    let kind = match &data[0..4] {
+    let mut magic = [0; 4];
+    magic.copy_from_slice(&data[0..4]);
+    let kind = match &magic {
         b"IWAD" => Ok(Kind::IWad),
         b"PWAD" => Ok(Kind::PWad),
-        _ => Err(Error::InvalidHeader),
+        _ => Err(Error::InvalidHeader { magic }),
     }?;
 
-    let n_entries = LittleEndian::read_i32(&data[4..8]);
-    let directory_offset = LittleEndian::read_i32(&data[8..12]);
+    let raw_n_entries = LittleEndian::read_i32(&data[4..8]);
+    let raw_directory_offset = LittleEndian::read_i32(&data[8..12]);
+    let invalid = || Error::Invalid {
+        n_entries: raw_n_entries,
+        directory_offset: raw_directory_offset,
+    };
 
-    if n_entries < 0 || directory_offset < 0 {
-        return Err(Error::Invalid);
+    if raw_n_entries < 0 || raw_directory_offset < 0 {
+        return Err(invalid());
     }
 
-    let n_entries = n_entries as usize;
-    let directory_offset = directory_offset as usize;
+    let n_entries = raw_n_entries as usize;
+    let directory_offset = raw_directory_offset as usize;
 
-    let expected_directory_length = n_entries
+    n_entries
         .checked_mul(DIRECTORY_ENTRY_BYTE_SIZE)
-        .ok_or(Error::Invalid)?;
-
-    let expected_binary_length = directory_offset
-        .checked_add(expected_directory_length)
-        .ok_or(Error::Invalid)?;
+        .and_then(|directory_length| directory_offset.checked_add(directory_length))
+        .ok_or_else(invalid)?;
 
-    if data.len() < expected_binary_length {
-        return Err(Error::InvalidLength);
-    }
-    data.truncate(expected_binary_length);
-
-    Ok(Wad {
+    Ok(Header {
         kind,
-        data,
-        directory_offset,
         n_entries,
+        directory_offset,
     })
 }
 
+/// Parse the header and verify that the directory it describes fits within
+/// `file_length` bytes. Only the first `HEADER_BYTE_SIZE` bytes of `data`
+/// are inspected.
+pub(crate) fn parse_header(data: &[u8], file_length: usize) -> Result<Header, Error> {
+    if file_length < HEADER_BYTE_SIZE {
+        return Err(Error::InvalidLength {
+            expected: HEADER_BYTE_SIZE,
+            actual: file_length,
+        });
+    }
+
+    let header = read_header(data)?;
+
+    if file_length < header.directory_end() {
+        return Err(Error::InvalidLength {
+            expected: header.directory_end(),
+            actual: file_length,
+        });
+    }
+
+    Ok(header)
+}
+
+fn directory(data: &[u8], directory_offset: usize, n_entries: usize) -> &[RawEntry] {
+    let directory =
+        &data[directory_offset..directory_offset + n_entries * DIRECTORY_ENTRY_BYTE_SIZE];
+
+    unsafe {
+        // This is safe because RawEntry has no alignment requirements, and
+        // the bounds of the slice were checked when taking it above
+
+        std::slice::from_raw_parts(directory.as_ptr() as *const RawEntry, n_entries)
+    }
+}
+
+impl Wad {
+    /// Construct a `Wad` from data with a valid header and directory, read
+    /// from a file of `file_length` bytes
+    pub(crate) fn from_owned(data: Vec<u8>, header: Header, file_length: usize) -> Wad {
+        Wad {
+            kind: header.kind,
+            data: Storage::Owned(data),
+            directory_offset: header.directory_offset,
+            n_entries: header.n_entries,
+            file_length,
+        }
+    }
+
+    /// The WAD file from the start of the header to the end of the
+    /// directory, excluding any trailing data
+    pub(crate) fn file_data(&self) -> &[u8] {
+        &self.data[..self.directory_offset + self.n_entries * DIRECTORY_ENTRY_BYTE_SIZE]
+    }
+}
+
+pub fn parse_wad(mut data: Vec<u8>) -> Result<Wad, Error> {
+    let file_length = data.len();
+    let header = parse_header(&data, file_length)?;
+    data.truncate(header.directory_end());
+
+    Ok(Wad::from_owned(data, header, file_length))
+}
+
+/// Like `parse_wad`, but borrows the data instead of taking ownership of it.
+/// This allows working with WAD files that are already in memory without
+/// copying them, including `&'static` data from `include_bytes!`.
+pub fn parse_wad_ref(data: &[u8]) -> Result<WadSlice<'_>, Error> {
+    let header = parse_header(data, data.len())?;
+
+    Ok(WadSlice::new(
+        &data[0..header.directory_offset],
+        directory(data, header.directory_offset, header.n_entries),
+        data.len(),
+    ))
+}
+
 pub fn load_wad_file(filename: impl AsRef<Path>) -> Result<Wad, LoadError> {
-    let data = std::fs::read(filename).map_err(LoadError::IoError)?;
-    parse_wad(data).map_err(LoadError::Error)
+    let filename = filename.as_ref();
+    let load = || -> Result<Wad, LoadError> {
+        let data = std::fs::read(filename)?;
+        Ok(parse_wad(data)?)
+    };
+    load().map_err(|e| e.with_path(filename))
+}
+
+/// Map the file into memory instead of reading it. The resulting `Wad` has
+/// the same API as one returned from `load_wad_file`.
+///
+/// The file must not be modified by this or any other process while the
+/// `Wad` is alive. Doing so is undefined behaviour.
+#[cfg(feature = "mmap")]
+pub fn load_wad_file_mmap(filename: impl AsRef<Path>) -> Result<Wad, LoadError> {
+    let filename = filename.as_ref();
+    let load = || -> Result<Wad, LoadError> {
+        let file = std::fs::File::open(filename)?;
+
+        // The file being modified while mapped is ruled out by the contract
+        // documented above
+        let map = unsafe { memmap2::Mmap::map(&file) }?;
+
+        let file_length = map.len();
+        let header = parse_header(&map, file_length)?;
+
+        Ok(Wad {
+            kind: header.kind,
+            data: Storage::Mapped(map),
+            directory_offset: header.directory_offset,
+            n_entries: header.n_entries,
+            file_length,
+        })
+    };
+    load().map_err(|e| e.with_path(filename))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{LoadErrorKind, WadBuilder};
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_gives_same_entries_as_read() {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        builder.push(b"E1M1", vec![]);
+        builder.push(b"THINGS", vec![1, 2, 3, 4]);
+
+        let path = std::env::temp_dir().join(format!("wad-mmap-test-{}.wad", std::process::id()));
+        std::fs::write(&path, builder.to_bytes().unwrap()).unwrap();
+
+        let read = load_wad_file(&path).unwrap();
+        let mapped = load_wad_file_mmap(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(mapped.kind(), read.kind());
+        let read: Vec<_> = read.entry_iter().map(|e| (e.id, e.lump.to_vec())).collect();
+        let mapped: Vec<_> = mapped
+            .entry_iter()
+            .map(|e| (e.id, e.lump.to_vec()))
+            .collect();
+        assert_eq!(mapped, read);
+    }
+
+    #[test]
+    fn parse_wad_ref_gives_same_entries_as_parse_wad() {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        builder.push(b"F_START", vec![]);
+        builder.push(b"FLOOR0_1", vec![7; 4096]);
+        builder.push(b"F_END", vec![]);
+        let data = builder.to_bytes().unwrap();
+
+        let borrowed = parse_wad_ref(&data).unwrap();
+        let owned = parse_wad(data.clone()).unwrap();
+
+        let borrowed: Vec<_> = borrowed.entry_iter().map(|e| (e.id, e.lump)).collect();
+        let owned: Vec<_> = owned.entry_iter().map(|e| (e.id, e.lump)).collect();
+        assert_eq!(borrowed, owned);
+    }
+
+    #[test]
+    fn parse_wad_ref_validates_like_parse_wad() {
+        let data = WadBuilder::new(Kind::PWad).to_bytes().unwrap();
+        assert!(parse_wad_ref(&data[..HEADER_BYTE_SIZE - 1]).is_err());
+        assert!(parse_wad_ref(b"JUNK\0\0\0\0\0\0\0\0").is_err());
+
+        let mut data = data;
+        data[4] = 1;
+        assert!(parse_wad_ref(&data).is_err());
+    }
+
+    #[test]
+    fn load_error_has_path() {
+        let path = std::env::temp_dir().join("wad-test-does-not-exist.wad");
+        let err = load_wad_file(&path).err().unwrap();
+        assert_eq!(err.path(), Some(path.as_path()));
+        assert!(matches!(err.kind(), LoadErrorKind::IoError(_)));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn invalid_length_has_sizes() {
+        let mut data = WadBuilder::new(Kind::PWad).to_bytes().unwrap();
+        data[4] = 2;
+        match parse_wad(data) {
+            Err(Error::InvalidLength { expected, actual }) => {
+                assert_eq!(
+                    (expected, actual),
+                    (HEADER_BYTE_SIZE + 32, HEADER_BYTE_SIZE)
+                );
+            }
+            _ => panic!("Expected InvalidLength"),
+        }
+    }
+
+    #[test]
+    fn trailing_data_after_directory_is_ignored() {
+        let mut builder = WadBuilder::new(Kind::IWad);
+        builder.push(b"PLAYPAL", vec![0; 768]);
+        let mut data = builder.to_bytes().unwrap();
+        data.extend_from_slice(b"garbage");
+
+        let wad = parse_wad(data).unwrap();
+        assert_eq!(wad.len(), 1);
+        assert_eq!(wad.lump(0).unwrap().len(), 768);
+    }
 }
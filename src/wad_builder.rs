@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use crate::entry_id::EntryId;
+use crate::wad::*;
+
+pub struct WadBuilder {
+    kind: Kind,
+    pub(crate) lumps: Vec<(EntryId, Vec<u8>)>,
+}
+
+impl WadBuilder {
+    pub fn new(kind: Kind) -> WadBuilder {
+        WadBuilder {
+            kind,
+            lumps: vec![],
+        }
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn set_kind(&mut self, kind: Kind) {
+        self.kind = kind;
+    }
+
+    pub fn len(&self) -> usize {
+        self.lumps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lumps.is_empty()
+    }
+
+    /// Append a lump to the directory. Zero-length lumps, such as map
+    /// headers and `*_START`/`*_END` markers, are written as directory
+    /// entries without any lump data.
+    pub fn push(&mut self, id: impl Into<EntryId>, lump: impl Into<Vec<u8>>) {
+        self.lumps.push((id.into(), lump.into()));
+    }
+
+    /// Serialize the WAD file: the header, followed by the lump data in
+    /// directory order, followed by the directory.
+    pub fn write_to(&self, w: impl Write) -> io::Result<()> {
+        write_wad(
+            self.kind,
+            self.lumps.iter().map(|(id, lump)| (*id, &lump[..])),
+            false,
+            w,
+        )
+        .map(|_| ())
+    }
+
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut buf = vec![];
+        self.write_to(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+impl std::iter::Extend<(EntryId, Vec<u8>)> for WadBuilder {
+    fn extend<T: IntoIterator<Item = (EntryId, Vec<u8>)>>(&mut self, iter: T) {
+        self.lumps.extend(iter);
+    }
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "WAD file too large")
+}
+
+// Offsets and lengths are stored as signed 32 bit integers in the file
+fn to_i32(x: usize) -> io::Result<i32> {
+    if x > i32::MAX as usize {
+        return Err(too_large());
+    }
+    Ok(x as i32)
+}
+
+/// What `write_wad` wrote
+pub(crate) struct Written {
+    /// Total number of bytes
+    pub size: usize,
+
+    /// Number of entries that share lump data with an earlier entry
+    pub shared_entries: usize,
+}
+
+/// Write a WAD file with the lump data in directory order. With
+/// `deduplicate`, lumps that are byte-identical to an earlier lump are not
+/// written again, and their directory entries point to the earlier copy.
+pub(crate) fn write_wad<'a>(
+    kind: Kind,
+    lumps: impl Iterator<Item = (EntryId, &'a [u8])>,
+    deduplicate: bool,
+    mut w: impl Write,
+) -> io::Result<Written> {
+    let lumps: Vec<_> = lumps.collect();
+
+    let mut directory = Vec::with_capacity(lumps.len());
+    let mut data = Vec::with_capacity(lumps.len());
+    let mut written = HashMap::new();
+    let mut shared_entries = 0;
+    let mut offset = HEADER_BYTE_SIZE;
+    for &(id, lump) in &lumps {
+        // Zero-length lumps are never shared, so they keep pointing to the
+        // current offset
+        if deduplicate && !lump.is_empty() {
+            if let Some(&start) = written.get(lump) {
+                directory.push((start, to_i32(lump.len())?, id));
+                shared_entries += 1;
+                continue;
+            }
+            written.insert(lump, to_i32(offset)?);
+        }
+
+        directory.push((to_i32(offset)?, to_i32(lump.len())?, id));
+        data.push(lump);
+        offset = offset.checked_add(lump.len()).ok_or_else(too_large)?;
+    }
+    let directory_offset = to_i32(offset)?;
+    let directory_length = lumps.len() * DIRECTORY_ENTRY_BYTE_SIZE;
+    let size = offset.checked_add(directory_length).ok_or_else(too_large)?;
+    to_i32(size)?;
+
+    w.write_all(kind.magic())?;
+    w.write_i32::<LittleEndian>(to_i32(lumps.len())?)?;
+    w.write_i32::<LittleEndian>(directory_offset)?;
+
+    for lump in data {
+        w.write_all(lump)?;
+    }
+
+    for (start, length, id) in directory {
+        w.write_i32::<LittleEndian>(start)?;
+        w.write_i32::<LittleEndian>(length)?;
+        w.write_all(id.as_bytes())?;
+    }
+
+    Ok(Written {
+        size,
+        shared_entries,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_wad;
+    use byteorder::ByteOrder;
+
+    fn sample() -> WadBuilder {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        builder.push(b"E1M1", vec![]);
+        builder.push(b"THINGS", vec![1, 2, 3, 4]);
+        builder.push(b"F_START", vec![]);
+        builder.push(b"FLOOR0_1", vec![5; 4096]);
+        builder.push(b"F_END", vec![]);
+        builder
+    }
+
+    #[test]
+    fn empty_wad_roundtrips() {
+        let data = WadBuilder::new(Kind::IWad).to_bytes().unwrap();
+        assert_eq!(data.len(), HEADER_BYTE_SIZE);
+
+        let wad = parse_wad(data).unwrap();
+        assert_eq!(wad.kind(), Kind::IWad);
+        assert!(wad.is_empty());
+    }
+
+    #[test]
+    fn roundtrips_through_parse_wad() {
+        let builder = sample();
+        let wad = parse_wad(builder.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(wad.kind(), Kind::PWad);
+        let entries: Vec<_> = wad.entry_iter().map(|e| (e.id, e.lump.to_vec())).collect();
+        assert_eq!(entries, builder.lumps);
+    }
+
+    #[test]
+    fn directory_follows_lump_data() {
+        let data = sample().to_bytes().unwrap();
+        let directory_offset = LittleEndian::read_i32(&data[8..12]) as usize;
+        assert_eq!(directory_offset, HEADER_BYTE_SIZE + 4 + 4096);
+        assert_eq!(data.len(), directory_offset + 5 * DIRECTORY_ENTRY_BYTE_SIZE);
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use crate::entry_id::EntryId;
+use crate::wad_slice::WadSlice;
+
+/// A hashed lookup table from `EntryId` to directory indices, for when many
+/// lookups are done in the same WAD. Indices are relative to the `WadSlice`
+/// the index was built from. IDs are compared with `EntryId::matches`.
+pub struct WadIndex {
+    map: HashMap<EntryId, Vec<usize>>,
+}
+
+impl WadIndex {
+    pub fn new(wad: &WadSlice) -> WadIndex {
+        let mut map: HashMap<_, Vec<_>> = HashMap::new();
+        for (index, id) in wad.id_iter().enumerate() {
+            map.entry(id.normalized()).or_default().push(index);
+        }
+        WadIndex { map }
+    }
+
+    /// All indices of entries with the given ID, in directory order
+    pub fn indices_of(&self, id: impl Into<EntryId>) -> &[usize] {
+        self.map
+            .get(&id.into().normalized())
+            .map(|x| &x[..])
+            .unwrap_or(&[])
+    }
+
+    /// Index of the first entry with the given ID
+    pub fn index_of(&self, id: impl Into<EntryId>) -> Option<usize> {
+        self.indices_of(id).first().cloned()
+    }
+
+    /// Index of the last entry with the given ID, as resolved by the Doom
+    /// engine
+    pub fn rindex_of(&self, id: impl Into<EntryId>) -> Option<usize> {
+        self.indices_of(id).last().cloned()
+    }
+
+    pub fn contains(&self, id: impl Into<EntryId>) -> bool {
+        self.map.contains_key(&id.into().normalized())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    fn sample() -> Wad {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        builder.push(b"E1M1", vec![]);
+        builder.push(b"THINGS", vec![1]);
+        builder.push(b"E1M2", vec![]);
+        builder.push(b"THINGS", vec![2]);
+        parse_wad(builder.to_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn indices_of_finds_all_in_order() {
+        let index = sample().build_index();
+        assert_eq!(index.indices_of(b"THINGS"), &[1, 3]);
+        assert_eq!(index.index_of(b"THINGS"), Some(1));
+        assert_eq!(index.rindex_of(b"THINGS"), Some(3));
+        assert_eq!(index.indices_of(b"SECTORS"), &[]);
+        assert!(!index.contains(b"SECTORS"));
+    }
+
+    #[test]
+    fn slice_index_is_relative_to_slice() {
+        let wad = sample();
+        let index = wad.slice(2..).build_index();
+        assert_eq!(index.indices_of(b"THINGS"), &[1]);
+        assert_eq!(index.index_of(b"E1M1"), None);
+    }
+
+    #[test]
+    fn agrees_with_linear_search() {
+        let wad = sample();
+        let index = wad.build_index();
+        for id in wad.id_iter() {
+            assert_eq!(index.index_of(id), wad.index_of(id));
+            assert_eq!(index.rindex_of(id), wad.rindex_of(id));
+            assert!(index
+                .indices_of(id)
+                .iter()
+                .cloned()
+                .eq(wad.all_indices_of(id)));
+        }
+    }
+}
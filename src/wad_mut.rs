@@ -0,0 +1,177 @@
+use std::io::{self, Write};
+
+use crate::entry_id::EntryId;
+use crate::error::Error;
+use crate::wad::*;
+use crate::wad_builder::WadBuilder;
+
+/// An editable WAD: a `WadBuilder` whose entries can also be changed.
+///
+/// Functions taking an index panic if it is out of bounds, like the
+/// corresponding functions on `Vec`.
+pub struct WadMut {
+    builder: WadBuilder,
+}
+
+impl WadMut {
+    pub fn new(kind: Kind) -> WadMut {
+        WadMut {
+            builder: WadBuilder::new(kind),
+        }
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.builder.kind()
+    }
+
+    pub fn set_kind(&mut self, kind: Kind) {
+        self.builder.set_kind(kind);
+    }
+
+    pub fn len(&self) -> usize {
+        self.builder.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.builder.is_empty()
+    }
+
+    pub fn entry_id(&self, index: usize) -> Option<EntryId> {
+        self.builder.lumps.get(index).map(|(id, _)| *id)
+    }
+
+    pub fn lump(&self, index: usize) -> Option<&[u8]> {
+        self.builder.lumps.get(index).map(|(_, lump)| &lump[..])
+    }
+
+    pub fn id_iter(&self) -> impl Iterator<Item = EntryId> + '_ {
+        self.builder.lumps.iter().map(|(id, _)| *id)
+    }
+
+    /// Index of the first entry with the given ID
+    pub fn index_of(&self, id: impl Into<EntryId>) -> Option<usize> {
+        let id = id.into();
+        self.id_iter().position(|x| x.matches(&id))
+    }
+
+    /// Index of the last entry with the given ID, as resolved by the Doom
+    /// engine
+    pub fn rindex_of(&self, id: impl Into<EntryId>) -> Option<usize> {
+        let id = id.into();
+        self.builder.lumps.iter().rposition(|(x, _)| x.matches(&id))
+    }
+
+    pub fn push(&mut self, id: impl Into<EntryId>, lump: impl Into<Vec<u8>>) {
+        self.builder.push(id, lump);
+    }
+
+    pub fn insert(&mut self, index: usize, id: impl Into<EntryId>, lump: impl Into<Vec<u8>>) {
+        self.builder.lumps.insert(index, (id.into(), lump.into()));
+    }
+
+    pub fn remove(&mut self, index: usize) -> (EntryId, Vec<u8>) {
+        self.builder.lumps.remove(index)
+    }
+
+    /// Returns the previous ID
+    pub fn rename(&mut self, index: usize, id: impl Into<EntryId>) -> EntryId {
+        std::mem::replace(&mut self.builder.lumps[index].0, id.into())
+    }
+
+    /// Returns the previous lump contents
+    pub fn replace(&mut self, index: usize, lump: impl Into<Vec<u8>>) -> Vec<u8> {
+        std::mem::replace(&mut self.builder.lumps[index].1, lump.into())
+    }
+
+    /// Move the entry at index `from` so it ends up at index `to`, shifting
+    /// the entries in between.
+    pub fn move_entry(&mut self, from: usize, to: usize) {
+        let entry = self.builder.lumps.remove(from);
+        self.builder.lumps.insert(to, entry);
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.builder.lumps.swap(a, b);
+    }
+
+    pub fn write_to(&self, w: impl Write) -> io::Result<()> {
+        self.builder.write_to(w)
+    }
+
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        self.builder.to_bytes()
+    }
+}
+
+impl Wad {
+    /// Copy all lumps into an editable representation. Fails if any
+    /// directory entry is invalid.
+    pub fn into_editable(self) -> Result<WadMut, Error> {
+        let mut wad = WadMut::new(self.kind());
+        for i in 0..self.len() {
+            let entry = self.entry(i)?;
+            wad.push(entry.id, entry.lump);
+        }
+
+        Ok(wad)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parse_wad, WadBuilder};
+
+    fn sample() -> WadMut {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        builder.push(b"E1M1", vec![]);
+        builder.push(b"THINGS", vec![1, 2, 3, 4]);
+        builder.push(b"LINEDEFS", vec![5, 6]);
+        parse_wad(builder.to_bytes().unwrap())
+            .unwrap()
+            .into_editable()
+            .unwrap()
+    }
+
+    fn ids(wad: &WadMut) -> Vec<String> {
+        wad.id_iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn into_editable_copies_entries() {
+        let wad = sample();
+        assert_eq!(wad.kind(), Kind::PWad);
+        assert_eq!(ids(&wad), ["E1M1", "THINGS", "LINEDEFS"]);
+        assert_eq!(wad.lump(1), Some(&[1, 2, 3, 4][..]));
+    }
+
+    #[test]
+    fn insert_remove_rename_replace() {
+        let mut wad = sample();
+        wad.insert(1, b"MAPINFO", b"x".to_vec());
+        assert_eq!(wad.remove(3).0, EntryId::from(b"LINEDEFS"));
+        assert_eq!(wad.rename(0, b"E1M2"), EntryId::from(b"E1M1"));
+        assert_eq!(wad.replace(2, vec![9]), vec![1, 2, 3, 4]);
+
+        assert_eq!(ids(&wad), ["E1M2", "MAPINFO", "THINGS"]);
+        assert_eq!(wad.lump(2), Some(&[9][..]));
+    }
+
+    #[test]
+    fn move_entry_reorders() {
+        let mut wad = sample();
+        wad.move_entry(0, 2);
+        assert_eq!(ids(&wad), ["THINGS", "LINEDEFS", "E1M1"]);
+        wad.move_entry(2, 0);
+        assert_eq!(ids(&wad), ["E1M1", "THINGS", "LINEDEFS"]);
+    }
+
+    #[test]
+    fn reserializes() {
+        let mut wad = sample();
+        wad.remove(2);
+        let parsed = parse_wad(wad.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed.by_id(b"THINGS"), Some(&[1, 2, 3, 4][..]));
+    }
+}
@@ -0,0 +1,174 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::entry_id::EntryId;
+use crate::error::{Error, LoadError};
+use crate::wad::*;
+use crate::wad_slice::{lump_range, WadSlice};
+
+/// Reads only the header and directory of a WAD file up front, and fetches
+/// individual lumps on demand. This is useful when only a few lumps are
+/// needed from a large file.
+pub struct WadReader<R> {
+    reader: R,
+    kind: Kind,
+    directory_offset: usize,
+    directory: Vec<RawEntry>,
+    file_length: usize,
+}
+
+impl<R: Read + Seek> WadReader<R> {
+    pub fn new(mut reader: R) -> Result<WadReader<R>, LoadError> {
+        let file_length = reader.seek(SeekFrom::End(0))?;
+        let file_length = file_length as usize;
+
+        let mut header = [0u8; HEADER_BYTE_SIZE];
+        if file_length >= HEADER_BYTE_SIZE {
+            reader.seek(SeekFrom::Start(0))?;
+            reader.read_exact(&mut header)?;
+        }
+        let header = parse_header(&header, file_length)?;
+
+        reader.seek(SeekFrom::Start(header.directory_offset as u64))?;
+        let mut directory = vec![[0u8; DIRECTORY_ENTRY_BYTE_SIZE]; header.n_entries];
+        for raw_entry in &mut directory {
+            reader.read_exact(raw_entry)?;
+        }
+
+        Ok(WadReader {
+            reader,
+            kind: header.kind,
+            directory_offset: header.directory_offset,
+            directory,
+            file_length,
+        })
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn len(&self) -> usize {
+        self.directory.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.directory.is_empty()
+    }
+
+    pub fn entry_id(&self, index: usize) -> Option<EntryId> {
+        let raw_entry = self.directory.get(index)?;
+        Some(WadSlice::entry_id_from_raw_entry(raw_entry))
+    }
+
+    pub fn id_iter(&self) -> impl Iterator<Item = EntryId> + '_ {
+        self.directory.iter().map(WadSlice::entry_id_from_raw_entry)
+    }
+
+    /// Index of the first entry with the given ID
+    pub fn index_of(&self, id: impl Into<EntryId>) -> Option<usize> {
+        let id = id.into();
+        self.id_iter().position(|x| x.matches(&id))
+    }
+
+    /// Index of the last entry with the given ID, as resolved by the Doom
+    /// engine
+    pub fn rindex_of(&self, id: impl Into<EntryId>) -> Option<usize> {
+        let id = id.into();
+        self.directory
+            .iter()
+            .rposition(|raw_entry| WadSlice::entry_id_from_raw_entry(raw_entry).matches(&id))
+    }
+
+    fn lump_range(&self, index: usize) -> Result<Range<usize>, Error> {
+        let raw_entry = self.directory.get(index).ok_or(Error::OutOfBounds {
+            index,
+            len: self.len(),
+        })?;
+        lump_range(raw_entry, self.directory_offset, self.file_length)
+            .map_err(|e| e.at_index(index))
+    }
+
+    /// The length of the lump at the given index, without reading it
+    pub fn lump_length(&self, index: usize) -> Result<usize, Error> {
+        Ok(self.lump_range(index)?.len())
+    }
+
+    pub fn read_lump(&mut self, index: usize) -> Result<Vec<u8>, LoadError> {
+        let range = self.lump_range(index)?;
+
+        let mut lump = vec![0; range.len()];
+        self.reader.seek(SeekFrom::Start(range.start as u64))?;
+        self.reader.read_exact(&mut lump)?;
+
+        Ok(lump)
+    }
+
+    /// Read the lump of the first entry with the given ID
+    pub fn by_id(&mut self, id: impl Into<EntryId>) -> Result<Option<Vec<u8>>, LoadError> {
+        match self.index_of(id) {
+            Some(index) => self.read_lump(index).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+pub fn open_wad_file(filename: impl AsRef<Path>) -> Result<WadReader<BufReader<File>>, LoadError> {
+    let filename = filename.as_ref();
+    let open = || -> Result<_, LoadError> {
+        let file = File::open(filename)?;
+        WadReader::new(BufReader::new(file))
+    };
+    open().map_err(|e| e.with_path(filename))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::WadBuilder;
+    use std::io::Cursor;
+
+    fn sample() -> Vec<u8> {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        builder.push(b"E1M1", vec![]);
+        builder.push(b"THINGS", vec![1, 2, 3, 4]);
+        builder.push(b"LINEDEFS", vec![5, 6]);
+        builder.to_bytes().unwrap()
+    }
+
+    #[test]
+    fn reads_directory_and_lumps() {
+        let mut reader = WadReader::new(Cursor::new(sample())).unwrap();
+        assert_eq!(reader.kind(), Kind::PWad);
+        assert_eq!(reader.len(), 3);
+        assert_eq!(reader.index_of(b"LINEDEFS"), Some(2));
+        assert_eq!(reader.lump_length(1).unwrap(), 4);
+        assert_eq!(reader.read_lump(2).unwrap(), vec![5, 6]);
+        assert_eq!(reader.by_id(b"THINGS").unwrap(), Some(vec![1, 2, 3, 4]));
+        assert_eq!(reader.read_lump(0).unwrap(), vec![]);
+        assert!(reader.by_id(b"SECTORS").unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_directory() {
+        let mut data = sample();
+        data.pop();
+        assert!(WadReader::new(Cursor::new(data)).is_err());
+    }
+
+    #[test]
+    fn rejects_lump_overlapping_directory() {
+        let mut data = sample();
+        let len = data.len();
+        // Make the length of LINEDEFS reach into the directory
+        data[len - 16 + 4] = 3;
+        let mut reader = WadReader::new(Cursor::new(data)).unwrap();
+        assert!(reader.read_lump(2).is_err());
+    }
+}
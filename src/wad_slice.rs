@@ -1,26 +1,60 @@
 use byteorder::{ByteOrder, LittleEndian};
+use std::ops::Range;
 use std::slice::SliceIndex;
 
 use crate::entry::Entry;
 use crate::entry_id::EntryId;
+use crate::entry_id_pattern::EntryIdPattern;
 use crate::error::Error;
 use crate::iterator::*;
+use crate::map::*;
+use crate::namespace::*;
+use crate::query::*;
 use crate::wad::*;
+use crate::wad_index::WadIndex;
 
+#[derive(Clone, Copy)]
 pub struct WadSlice<'a> {
     data: &'a [u8],
     directory: &'a [RawEntry],
+    file_length: usize,
 }
 
 impl<'a> WadSlice<'a> {
-    pub(crate) fn new<'n>(data: &'n [u8], directory: &'n [RawEntry]) -> WadSlice<'n> {
-        WadSlice { data, directory }
+    pub(crate) fn new<'n>(
+        data: &'n [u8],
+        directory: &'n [RawEntry],
+        file_length: usize,
+    ) -> WadSlice<'n> {
+        WadSlice {
+            data,
+            directory,
+            file_length,
+        }
+    }
+
+    /// The lump data, which ends where the directory starts
+    pub(crate) fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// The length of the file the slice was read from
+    pub(crate) fn file_length(&self) -> usize {
+        self.file_length
+    }
+
+    pub(crate) fn raw_directory(&self) -> &'a [RawEntry] {
+        self.directory
     }
 
     pub fn len(&self) -> usize {
         self.directory.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.directory.is_empty()
+    }
+
     pub fn entry_id_from_raw_entry(raw_entry: &RawEntry) -> EntryId {
         // This is safe because the static size of RawEntry is bigger than
         // the size of the requested slice:
@@ -28,6 +62,9 @@ impl<'a> WadSlice<'a> {
         EntryId::from_bytes(id)
     }
 
+    /// # Safety
+    ///
+    /// `index` must be less than `self.len()`.
     pub unsafe fn entry_id_unchecked(&self, index: usize) -> EntryId {
         let directory_entry = self.directory.get_unchecked(index);
         Self::entry_id_from_raw_entry(directory_entry)
@@ -38,56 +75,106 @@ impl<'a> WadSlice<'a> {
         Some(Self::entry_id_from_raw_entry(directory_entry))
     }
 
-    pub fn id_iter(&self) -> SliceIdIterator {
+    pub fn id_iter(&self) -> SliceIdIterator<'_> {
         SliceIdIterator::new(self)
     }
 
+    /// Index of the first entry with the given ID. IDs are compared with
+    /// `EntryId::matches`, here and in the other lookup functions.
     pub fn index_of(&self, id: impl Into<EntryId>) -> Option<usize> {
-        let id = id.into();
-        self.id_iter().position(|x| x == id)
+        let id = id.into().normalized();
+        self.id_iter().position(|x| x.normalized() == id)
     }
 
-    pub fn entry_from_raw_entry(&self, raw_entry: &RawEntry) -> Result<Entry<'a>, Error> {
-        let start = LittleEndian::read_i32(&raw_entry[0..4]);
-        let length = LittleEndian::read_i32(&raw_entry[4..8]);
-        let id = Self::entry_id_from_raw_entry(raw_entry);
-
-        verify!(length >= 0, Error::InvalidEntry);
-        let length = length as usize;
-
-        verify!(start >= 0, Error::InvalidEntry);
-        let mut start = start as usize;
+    /// Index of the last entry with the given ID. This is how the Doom engine
+    /// resolves lump names, so that later lumps override earlier ones.
+    pub fn rindex_of(&self, id: impl Into<EntryId>) -> Option<usize> {
+        let id = id.into().normalized();
+        self.directory
+            .iter()
+            .rposition(|raw_entry| Self::entry_id_from_raw_entry(raw_entry).normalized() == id)
+    }
 
-        // If length == 0, start doesn't matter. Some directory entries in
-        // official doom wads have start == 0, which is really too early.
-        if length == 0 {
-            start = HEADER_BYTE_SIZE;
-        }
+    /// Indices of all entries with the given ID, in directory order
+    pub fn all_indices_of(&self, id: impl Into<EntryId>) -> impl Iterator<Item = usize> + 'a {
+        let id = id.into().normalized();
+        self.directory
+            .iter()
+            .enumerate()
+            .filter(move |(_, raw_entry)| {
+                Self::entry_id_from_raw_entry(raw_entry).normalized() == id
+            })
+            .map(|(index, _)| index)
+    }
 
-        verify!(start >= HEADER_BYTE_SIZE, Error::InvalidEntry);
+    /// All entries with names matching the pattern, in directory order,
+    /// along with their indices
+    pub fn find_all(
+        &self,
+        pattern: &EntryIdPattern,
+    ) -> impl Iterator<Item = Result<(usize, Entry<'a>), Error>> + 'a {
+        let wad = *self;
+        let pattern = pattern.clone();
+        (0..self.len())
+            .filter(move |&index| pattern.matches(&wad.entry_id(index).unwrap()))
+            .map(move |index| Ok((index, wad.entry(index)?)))
+    }
 
-        let end = start.checked_add(length).ok_or(Error::InvalidEntry)?;
-        verify!(end <= self.data.len(), Error::InvalidEntry);
+    /// Build a hashed index for faster lookups than `index_of`
+    pub fn build_index(&self) -> WadIndex {
+        WadIndex::new(self)
+    }
 
-        let lump = &self.data[start..end];
+    pub fn entry_from_raw_entry(&self, raw_entry: &RawEntry) -> Result<Entry<'a>, Error> {
+        let id = Self::entry_id_from_raw_entry(raw_entry);
+        let lump = &self.data[lump_range(raw_entry, self.data.len(), self.file_length)?];
 
         Ok(Entry { id, lump })
     }
 
+    /// # Safety
+    ///
+    /// `index` must be less than `self.len()`.
     pub unsafe fn entry_unchecked(&self, index: usize) -> Result<Entry<'a>, Error> {
         let raw_entry = self.directory.get_unchecked(index);
         self.entry_from_raw_entry(raw_entry)
+            .map_err(|e| e.at_index(index))
     }
 
     pub fn entry(&self, index: usize) -> Result<Entry<'a>, Error> {
-        let raw_entry = self.directory.get(index).ok_or(Error::OutOfBounds)?;
+        let raw_entry = self.directory.get(index).ok_or(Error::OutOfBounds {
+            index,
+            len: self.len(),
+        })?;
         self.entry_from_raw_entry(raw_entry)
+            .map_err(|e| e.at_index(index))
     }
 
-    pub fn entry_iter(&self) -> SliceEntryIterator {
+    pub fn lump(&self, index: usize) -> Result<&'a [u8], Error> {
+        Ok(self.entry(index)?.lump)
+    }
+
+    /// The location of the lump in the file. Zero-length lumps are placed
+    /// right after the header, whatever their directory entry says.
+    pub fn lump_range(&self, index: usize) -> Result<Range<usize>, Error> {
+        let raw_entry = self.directory.get(index).ok_or(Error::OutOfBounds {
+            index,
+            len: self.len(),
+        })?;
+        lump_range(raw_entry, self.data.len(), self.file_length).map_err(|e| e.at_index(index))
+    }
+
+    /// Panics when reaching an invalid entry. See `try_entry_iter` for a
+    /// non-panicking alternative.
+    pub fn entry_iter(&self) -> SliceEntryIterator<'_> {
         SliceEntryIterator::new(self)
     }
 
+    pub fn try_entry_iter(&self) -> SliceTryEntryIterator<'_> {
+        SliceTryEntryIterator::new(self)
+    }
+
+    /// The lump of the first entry with the given ID
     pub fn by_id(&self, id: impl Into<EntryId>) -> Option<&'a [u8]> {
         let id = id.into();
         let index = self.index_of(id)?;
@@ -95,14 +182,69 @@ impl<'a> WadSlice<'a> {
         Some(entry.lump)
     }
 
+    /// The lump of the last entry with the given ID, as resolved by the
+    /// Doom engine
+    pub fn by_id_last(&self, id: impl Into<EntryId>) -> Option<&'a [u8]> {
+        let id = id.into();
+        let index = self.rindex_of(id)?;
+        let entry = self.entry(index).ok()?;
+        Some(entry.lump)
+    }
+
+    /// The tree of namespaces delimited by `X_START`/`X_END` markers
+    pub fn namespaces(&self) -> Vec<Namespace<'a>> {
+        parse_namespaces(self)
+    }
+
+    /// The first namespace with the given name, searching nested
+    /// namespaces too. Names are case-insensitive, and doubled letters
+    /// match the single letter variants the way Boom-compatible engines
+    /// do, so `"S"` finds both `S_START` and `SS_START` sections.
+    pub fn namespace(&self, name: &str) -> Option<Namespace<'a>> {
+        self.all_namespaces(name).into_iter().next()
+    }
+
+    /// All namespaces with the given name, in directory order. See
+    /// `namespace`.
+    pub fn all_namespaces(&self, name: &str) -> Vec<Namespace<'a>> {
+        let name = normalize_namespace_name(name);
+        let namespaces = self.namespaces();
+        let mut found = vec![];
+        find_namespaces(&namespaces, &name, &mut found);
+        found.into_iter().cloned().collect()
+    }
+
+    /// All maps in directory order, detected by the lumps following each
+    /// map header
+    pub fn maps(&self) -> Vec<MapRef<'a>> {
+        find_maps(self)
+    }
+
+    /// The first map with the given name
+    pub fn map(&self, name: impl Into<EntryId>) -> Option<MapRef<'a>> {
+        let name = name.into();
+        self.maps().into_iter().find(|map| map.name.matches(&name))
+    }
+
+    /// Locate the entry described by the query. See `Query` for the
+    /// query syntax.
+    pub fn query(&self, query: &Query) -> Result<Entry<'a>, QueryError> {
+        run_query(self, query)
+    }
+
+    /// A view of some of the directory entries. Indices into the result,
+    /// including those reported in errors, are relative to the start of the
+    /// range.
     pub fn slice(
         &self,
         slice_index: impl SliceIndex<[RawEntry], Output = [RawEntry]>,
     ) -> WadSlice<'a> {
-        WadSlice::new(self.data, &self.directory[slice_index])
+        WadSlice::new(self.data, &self.directory[slice_index], self.file_length)
     }
 }
 
+/// Panics if the index is out of bounds or the entry is invalid. See
+/// `WadSlice::lump` for a non-panicking alternative.
 impl<'a> std::ops::Index<usize> for WadSlice<'a> {
     type Output = [u8];
 
@@ -111,8 +253,122 @@ impl<'a> std::ops::Index<usize> for WadSlice<'a> {
     }
 }
 
+/// Validate the start and length of a directory entry, given that the lump
+/// data must lie between the header and `directory_offset`. `file_length`
+/// is only used for the error message.
+pub(crate) fn lump_range(
+    raw_entry: &RawEntry,
+    directory_offset: usize,
+    file_length: usize,
+) -> Result<Range<usize>, Error> {
+    let raw_start = LittleEndian::read_i32(&raw_entry[0..4]);
+    let raw_length = LittleEndian::read_i32(&raw_entry[4..8]);
+    let invalid = || Error::InvalidEntry {
+        index: None,
+        id: WadSlice::entry_id_from_raw_entry(raw_entry),
+        start: raw_start,
+        length: raw_length,
+        directory_offset,
+        file_length,
+    };
+
+    verify!(raw_length >= 0, invalid());
+    let length = raw_length as usize;
+
+    verify!(raw_start >= 0, invalid());
+    let mut start = raw_start as usize;
+
+    // If length == 0, start doesn't matter. Some directory entries in
+    // official doom wads have start == 0, which is really too early.
+    if length == 0 {
+        start = HEADER_BYTE_SIZE;
+    }
+
+    verify!(start >= HEADER_BYTE_SIZE, invalid());
+
+    let end = start.checked_add(length).ok_or_else(invalid)?;
+    verify!(end <= directory_offset, invalid());
+
+    Ok(start..end)
+}
+
 impl<'a> From<&'a Wad> for WadSlice<'a> {
     fn from(wad: &'a Wad) -> WadSlice<'a> {
         wad.as_slice()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    fn sample() -> Wad {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        builder.push(b"PLAYPAL", vec![1]);
+        builder.push(b"E1M1", vec![]);
+        builder.push(b"PLAYPAL", vec![2]);
+        builder.push(b"ENDOOM", vec![3]);
+        parse_wad(builder.to_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn first_and_last_lookups() {
+        let wad = sample();
+        assert_eq!(wad.index_of(b"PLAYPAL"), Some(0));
+        assert_eq!(wad.rindex_of(b"PLAYPAL"), Some(2));
+        assert_eq!(wad.by_id(b"PLAYPAL"), Some(&[1][..]));
+        assert_eq!(wad.by_id_last(b"PLAYPAL"), Some(&[2][..]));
+        assert_eq!(wad.rindex_of(b"COLORMAP"), None);
+    }
+
+    #[test]
+    fn lump_ranges_are_file_offsets() {
+        let wad = sample();
+        assert_eq!(wad.lump_range(0).unwrap(), 12..13);
+        assert_eq!(wad.lump_range(1).unwrap(), 12..12);
+        assert_eq!(wad.lump_range(3).unwrap(), 14..15);
+        assert!(wad.lump_range(4).is_err());
+    }
+
+    #[test]
+    fn all_indices_of_is_relative_to_slice() {
+        let wad = sample();
+        assert_eq!(wad.all_indices_of(b"PLAYPAL").collect::<Vec<_>>(), [0, 2]);
+        assert_eq!(
+            wad.slice(1..)
+                .all_indices_of(b"PLAYPAL")
+                .collect::<Vec<_>>(),
+            [1]
+        );
+        assert_eq!(wad.slice(1..).rindex_of(b"E1M1"), Some(0));
+    }
+
+    #[test]
+    fn find_all_matches_pattern() {
+        let wad = sample();
+        let pattern = EntryIdPattern::new("*PAL").unwrap();
+        let found: Vec<_> = wad
+            .find_all(&pattern)
+            .map(|x| {
+                let (index, entry) = x.unwrap();
+                (index, entry.lump)
+            })
+            .collect();
+        assert_eq!(found, [(0, &[1][..]), (2, &[2][..])]);
+        assert_eq!(wad.slice(1..).find_all(&pattern).count(), 1);
+    }
+
+    #[test]
+    fn lookups_are_normalized() {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        builder.push(b"e1m1", vec![]);
+        builder.push(b"E1M1\0xyz", vec![1]);
+        let wad = parse_wad(builder.to_bytes().unwrap()).unwrap();
+
+        let id = EntryId::from_str("E1M1").unwrap();
+        assert_eq!(wad.index_of(id), Some(0));
+        assert_eq!(wad.rindex_of(id), Some(1));
+        assert_eq!(wad.all_indices_of(b"e1M1").count(), 2);
+        assert_eq!(wad.entry_id(1).unwrap().as_bytes(), b"E1M1\0xyz");
+    }
+}
@@ -0,0 +1,205 @@
+use crate::entry::Entry;
+use crate::entry_id::EntryId;
+use crate::error::Error;
+use crate::wad::Wad;
+
+/// An IWAD and any number of PWADs in load order. Lookups resolve names
+/// the way the engine does: later files override earlier ones, and within
+/// a file the last entry with a given name wins.
+#[derive(Default)]
+pub struct WadStack {
+    wads: Vec<Wad>,
+}
+
+pub struct StackEntry<'a> {
+    /// Index of the WAD in the stack
+    pub wad_index: usize,
+
+    /// Index of the entry in the directory of its WAD
+    pub index: usize,
+
+    pub entry: Entry<'a>,
+}
+
+impl WadStack {
+    pub fn new() -> WadStack {
+        WadStack { wads: vec![] }
+    }
+
+    /// Add a WAD on top of the stack, overriding the ones already loaded
+    pub fn push(&mut self, wad: Wad) {
+        self.wads.push(wad);
+    }
+
+    pub fn len(&self) -> usize {
+        self.wads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.wads.is_empty()
+    }
+
+    pub fn wads(&self) -> &[Wad] {
+        &self.wads
+    }
+
+    fn stack_entry(&self, wad_index: usize, index: usize) -> Result<StackEntry<'_>, Error> {
+        let entry = self.wads[wad_index].entry(index)?;
+        Ok(StackEntry {
+            wad_index,
+            index,
+            entry,
+        })
+    }
+
+    /// Find the entry the engine would use for the given ID. Fails if that
+    /// entry is invalid, rather than falling back to an earlier one.
+    pub fn by_id(&self, id: impl Into<EntryId>) -> Result<Option<StackEntry<'_>>, Error> {
+        let id = id.into();
+        self.wads
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(wad_index, wad)| {
+                let index = wad.rindex_of(id)?;
+                Some((wad_index, index))
+            })
+            .map(|(wad_index, index)| self.stack_entry(wad_index, index))
+            .transpose()
+    }
+
+    /// Find the entry the engine would use for the given ID within a
+    /// namespace, such as `"S"` for sprites between `S_START` and `S_END`.
+    /// Namespaces are matched as by `WadSlice::namespace`. Fails like
+    /// `by_id`.
+    pub fn namespace_by_id(
+        &self,
+        namespace: &str,
+        id: impl Into<EntryId>,
+    ) -> Result<Option<StackEntry<'_>>, Error> {
+        let id = id.into();
+        self.wads
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(wad_index, wad)| {
+                wad.as_slice()
+                    .all_namespaces(namespace)
+                    .into_iter()
+                    .rev()
+                    .find_map(|namespace| {
+                        let index = namespace.entries.rindex_of(id)?;
+                        Some((wad_index, namespace.entries_start() + index))
+                    })
+            })
+            .map(|(wad_index, index)| self.stack_entry(wad_index, index))
+            .transpose()
+    }
+
+    /// Iterate over all entries of all WADs in load order, including the
+    /// ones that are overridden. Panics when reaching an invalid entry. See
+    /// `try_entry_iter` for a non-panicking alternative.
+    pub fn entry_iter(&self) -> impl Iterator<Item = StackEntry<'_>> {
+        self.try_entry_iter().map(Result::unwrap)
+    }
+
+    pub fn try_entry_iter(&self) -> impl Iterator<Item = Result<StackEntry<'_>, Error>> {
+        self.wads.iter().enumerate().flat_map(|(wad_index, wad)| {
+            wad.try_entry_iter().enumerate().map(move |(index, entry)| {
+                Ok(StackEntry {
+                    wad_index,
+                    index,
+                    entry: entry?,
+                })
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parse_wad, Kind, WadBuilder};
+
+    fn wad(lumps: &[(&str, u8)]) -> Wad {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        for &(name, content) in lumps {
+            let lump = if content == 0 { vec![] } else { vec![content] };
+            builder.push(EntryId::from_str(name).unwrap(), lump);
+        }
+        parse_wad(builder.to_bytes().unwrap()).unwrap()
+    }
+
+    fn sample() -> WadStack {
+        let mut stack = WadStack::new();
+        stack.push(wad(&[
+            ("PLAYPAL", 1),
+            ("S_START", 0),
+            ("TROOA1", 2),
+            ("S_END", 0),
+        ]));
+        stack.push(wad(&[("PLAYPAL", 3), ("PLAYPAL", 4), ("TROOA1", 5)]));
+        stack.push(wad(&[("SS_START", 0), ("TROOA1", 6), ("SS_END", 0)]));
+        stack
+    }
+
+    #[test]
+    fn later_wads_override_earlier() {
+        let stack = sample();
+        let entry = stack.by_id(b"PLAYPAL").unwrap().unwrap();
+        assert_eq!((entry.wad_index, entry.index), (1, 1));
+        assert_eq!(entry.entry.lump, &[4]);
+        assert!(stack.by_id(b"COLORMAP").unwrap().is_none());
+    }
+
+    #[test]
+    fn invalid_entry_is_reported() {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        builder.push(b"PLAYPAL", vec![7]);
+        let mut data = builder.to_bytes().unwrap();
+        // Make the length of the entry negative
+        let directory_offset = data.len() - 16;
+        data[directory_offset + 7] = 0x80;
+
+        let mut stack = sample();
+        stack.push(parse_wad(data).unwrap());
+        match stack.by_id(b"PLAYPAL") {
+            Err(Error::InvalidEntry { index, .. }) => assert_eq!(index, Some(0)),
+            _ => panic!("Expected InvalidEntry"),
+        }
+    }
+
+    #[test]
+    fn namespace_lookup_ignores_lumps_outside_namespace() {
+        let stack = sample();
+        let entry = stack.namespace_by_id("S", b"TROOA1").unwrap().unwrap();
+        assert_eq!((entry.wad_index, entry.index), (2, 1));
+
+        let mut stack = sample();
+        stack.wads.pop();
+        let entry = stack.namespace_by_id("S", b"TROOA1").unwrap().unwrap();
+        assert_eq!((entry.wad_index, entry.index), (0, 2));
+        assert!(stack.namespace_by_id("F", b"TROOA1").unwrap().is_none());
+    }
+
+    #[test]
+    fn entry_iter_covers_all_wads_in_order() {
+        let stack = sample();
+        let sources: Vec<_> = stack.entry_iter().map(|e| (e.wad_index, e.index)).collect();
+        assert_eq!(
+            sources,
+            [
+                (0, 0),
+                (0, 1),
+                (0, 2),
+                (0, 3),
+                (1, 0),
+                (1, 1),
+                (1, 2),
+                (2, 0),
+                (2, 1),
+                (2, 2)
+            ]
+        );
+    }
+}
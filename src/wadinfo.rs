@@ -0,0 +1,682 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+
+use crate::entry_id::{EntryId, EntryIdError};
+use crate::manifest::*;
+use crate::namespace::Namespace;
+use crate::wad::*;
+use crate::wad_slice::WadSlice;
+
+/// The file name DeuTex uses for the WAD description
+pub const WADINFO_FILE_NAME: &str = "wadinfo.txt";
+
+/// A section of a DeuTex `wadinfo.txt`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Section {
+    /// Map names. The lumps of each map are not listed.
+    Levels,
+
+    /// Lumps without any special handling
+    Lumps,
+    Sounds,
+    Musics,
+
+    /// `TEXTURE1` and `TEXTURE2`
+    Textures,
+
+    Graphics,
+
+    /// Lumps in the `S_START`/`S_END` namespace
+    Sprites,
+
+    /// Lumps in the `P_START`/`P_END` namespace
+    Patches,
+
+    /// Lumps in the `F_START`/`F_END` namespace
+    Flats,
+
+    /// Any other section, by its name in lowercase. Its entries are treated
+    /// like those in `Lumps`.
+    Other(String),
+}
+
+impl Section {
+    pub fn name(&self) -> &str {
+        match self {
+            Section::Levels => "levels",
+            Section::Lumps => "lumps",
+            Section::Sounds => "sounds",
+            Section::Musics => "musics",
+            Section::Textures => "textures",
+            Section::Graphics => "graphics",
+            Section::Sprites => "sprites",
+            Section::Patches => "patches",
+            Section::Flats => "flats",
+            Section::Other(name) => name,
+        }
+    }
+
+    fn from_name(name: &str) -> Section {
+        let name = name.to_ascii_lowercase();
+        match &name[..] {
+            "levels" => Section::Levels,
+            "lumps" => Section::Lumps,
+            "sounds" => Section::Sounds,
+            "musics" => Section::Musics,
+            "textures" => Section::Textures,
+            "graphics" => Section::Graphics,
+            "sprites" => Section::Sprites,
+            "patches" => Section::Patches,
+            "flats" => Section::Flats,
+            _ => Section::Other(name),
+        }
+    }
+
+    /// Whether the lumps of this section can be zero-length, and so may
+    /// have no lump file
+    fn allows_empty(&self) -> bool {
+        matches!(self, Section::Lumps | Section::Other(_))
+    }
+
+    /// The namespace the lumps of this section are stored in
+    fn namespace(&self) -> Option<&'static str> {
+        match self {
+            Section::Sprites => Some("S"),
+            Section::Patches => Some("P"),
+            Section::Flats => Some("F"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WadInfoEntry {
+    pub id: EntryId,
+
+    /// The insertion point of a picture, as given after the name
+    pub offset: Option<(i32, i32)>,
+
+    /// Written as `*` after the name. The lump has the same data as the
+    /// previous entry.
+    pub same_as_previous: bool,
+}
+
+impl WadInfoEntry {
+    pub fn new(id: EntryId) -> WadInfoEntry {
+        WadInfoEntry {
+            id,
+            offset: None,
+            same_as_previous: false,
+        }
+    }
+}
+
+/// The contents of a DeuTex `wadinfo.txt`, which lists the entries of a WAD
+/// by section:
+///
+/// ```text
+/// # Comment
+/// [levels]
+/// E1M1
+/// [sprites]
+/// TROOA1 19 57
+/// TROOA2 *
+/// ```
+///
+/// Names are written like in a `Manifest`, so unusual names survive a round
+/// trip.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct WadInfo {
+    pub sections: Vec<(Section, Vec<WadInfoEntry>)>,
+}
+
+#[derive(Debug)]
+pub enum WadInfoError {
+    /// An entry before the first section header
+    EntryOutsideSection { line: usize },
+
+    /// The line does not consist of a name, optionally followed by `*` or
+    /// two integers
+    InvalidLine { line: usize, text: String },
+
+    InvalidName {
+        line: usize,
+        name: String,
+        reason: EntryIdError,
+    },
+}
+
+impl std::fmt::Display for WadInfoError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WadInfoError::EntryOutsideSection { line } => {
+                write!(fmt, "Entry outside of any section on line {}", line)
+            }
+            WadInfoError::InvalidLine { line, text } => {
+                write!(fmt, "Invalid wadinfo line {}: {:?}", line, text)
+            }
+            WadInfoError::InvalidName { line, name, reason } => write!(
+                fmt,
+                "Invalid lump name on wadinfo line {}: {:?}: {}",
+                line, name, reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WadInfoError {}
+
+/// The section for an entry that is neither in a map nor a namespace
+fn section_for(id: &EntryId) -> Section {
+    let id = id.normalized();
+    let name = id.as_bytes();
+    if &name[..] == b"TEXTURE1" || &name[..] == b"TEXTURE2" {
+        Section::Textures
+    } else if name.starts_with(b"DS") || name.starts_with(b"DP") {
+        Section::Sounds
+    } else if name.starts_with(b"D_") {
+        Section::Musics
+    } else {
+        Section::Lumps
+    }
+}
+
+/// Exclude the markers of `namespace` and all namespaces nested within it
+fn exclude_markers(namespace: &Namespace, sections: &mut [Option<Section>]) {
+    sections[namespace.start] = None;
+    if let Some(end) = namespace.end {
+        sections[end] = None;
+    }
+    for child in &namespace.children {
+        exclude_markers(child, sections);
+    }
+}
+
+fn assign_namespace_sections(namespaces: &[Namespace], sections: &mut [Option<Section>]) {
+    for namespace in namespaces {
+        let section = match &namespace.name[..] {
+            "S" => Section::Sprites,
+            "P" => Section::Patches,
+            "F" => Section::Flats,
+            _ => {
+                assign_namespace_sections(&namespace.children, sections);
+                continue;
+            }
+        };
+
+        let start = namespace.entries_start();
+        for entry_section in &mut sections[start..start + namespace.entries.len()] {
+            *entry_section = Some(section.clone());
+        }
+        exclude_markers(namespace, sections);
+    }
+}
+
+/// `dir` and the directories below it, depth first in order of name.
+/// `maps` and the sprite, patch and flat directories are skipped at the top
+/// level, since their lumps are listed in other sections.
+fn lump_directories(root: &Path, dir: &str, dirs: &mut Vec<String>) -> io::Result<()> {
+    dirs.push(dir.to_string());
+    if !root.join(dir).is_dir() {
+        return Ok(());
+    }
+
+    let skipped = [
+        MAPS_DIRECTORY.to_string(),
+        namespace_directory("S"),
+        namespace_directory("P"),
+        namespace_directory("F"),
+    ];
+    for child in read_directory(root, dir)?.directories {
+        if dir.is_empty() && skipped.contains(&child) {
+            continue;
+        }
+        lump_directories(root, &format!("{}{}/", dir, child), dirs)?;
+    }
+    Ok(())
+}
+
+/// The order DeuTex writes sections in
+const SECTION_ORDER: &[Section] = &[
+    Section::Levels,
+    Section::Lumps,
+    Section::Sounds,
+    Section::Musics,
+    Section::Textures,
+    Section::Graphics,
+    Section::Sprites,
+    Section::Patches,
+    Section::Flats,
+];
+
+impl WadInfo {
+    /// Describe the entries of `wad` by section. Maps are listed in
+    /// `[levels]`, and the lumps in the sprite, patch and flat namespaces in
+    /// their sections, without markers. Other lumps are sorted into
+    /// `[textures]`, `[sounds]` and `[musics]` by name, or `[lumps]`.
+    /// Pictures are not told apart from other lumps, so `[graphics]` is
+    /// always empty.
+    pub fn from_wad(wad: &WadSlice) -> WadInfo {
+        let mut sections: Vec<_> = wad.id_iter().map(|id| Some(section_for(&id))).collect();
+
+        assign_namespace_sections(&wad.namespaces(), &mut sections);
+
+        for map in wad.maps() {
+            sections[map.index] = Some(Section::Levels);
+            for entry_section in &mut sections[map.index + 1..map.index + 1 + map.lumps.len()] {
+                *entry_section = None;
+            }
+        }
+
+        let mut wadinfo = WadInfo::default();
+        for section in SECTION_ORDER {
+            let entries: Vec<_> = wad
+                .id_iter()
+                .zip(&sections)
+                .filter(|(_, x)| x.as_ref() == Some(section))
+                .map(|(id, _)| WadInfoEntry::new(id))
+                .collect();
+
+            if !entries.is_empty() {
+                wadinfo.sections.push((section.clone(), entries));
+            }
+        }
+        wadinfo
+    }
+
+    pub fn parse(text: &str) -> Result<WadInfo, WadInfoError> {
+        let mut wadinfo = WadInfo::default();
+
+        for (index, text) in text.lines().enumerate() {
+            let line = index + 1;
+            let text = text.trim();
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = text.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+                wadinfo
+                    .sections
+                    .push((Section::from_name(name.trim()), vec![]));
+                continue;
+            }
+
+            let invalid_line = || WadInfoError::InvalidLine {
+                line,
+                text: text.to_string(),
+            };
+
+            let fields: Vec<_> = text.split_whitespace().collect();
+            let (name, offset, same_as_previous) = match fields[..] {
+                [name] => (name, None, false),
+                [name, "*"] => (name, None, true),
+                [name, x, y] => {
+                    let x = x.parse().map_err(|_| invalid_line())?;
+                    let y = y.parse().map_err(|_| invalid_line())?;
+                    (name, Some((x, y)), false)
+                }
+                _ => return Err(invalid_line()),
+            };
+
+            let id = EntryId::from_escaped(name).map_err(|reason| WadInfoError::InvalidName {
+                line,
+                name: name.to_string(),
+                reason,
+            })?;
+
+            let (_, entries) = wadinfo
+                .sections
+                .last_mut()
+                .ok_or(WadInfoError::EntryOutsideSection { line })?;
+            entries.push(WadInfoEntry {
+                id,
+                offset,
+                same_as_previous,
+            });
+        }
+
+        Ok(wadinfo)
+    }
+
+    /// Map the entries to a `Manifest` for the directory tree at `root`, as
+    /// laid out by `Manifest::new`. The entries come in the order of the
+    /// sections. Each level is expanded to the lumps found in its directory
+    /// under `maps`, ordered like `Manifest::from_directory` does. Sprites,
+    /// patches and flats get namespace markers around them.
+    ///
+    /// The lump file of an entry is looked for in the directory of its
+    /// section, then in the directories below it, where `Manifest::new` puts
+    /// lumps of nested namespaces such as `flats/f1`. Entries marked as the
+    /// same as the previous entry use the same file.
+    ///
+    /// Entries in `[lumps]` and unknown sections without a lump file are
+    /// zero-length, such as markers, since `Manifest::new` writes no file
+    /// for those. In the other sections, a missing lump file is a
+    /// `NotFound` error, since packing would give an empty lump.
+    pub fn to_manifest(&self, kind: Kind, root: &Path) -> io::Result<Manifest> {
+        let mut used = HashSet::new();
+        let mut entries: Vec<ManifestEntry> = vec![];
+
+        let marker = |namespace: &str, suffix: &str| ManifestEntry {
+            id: EntryId::from_str(format!("{}{}", namespace, suffix)).unwrap(),
+            path: None,
+        };
+
+        for (section, section_entries) in &self.sections {
+            if *section == Section::Levels {
+                for entry in section_entries {
+                    push_map(root, &lump_file_name(&entry.id), &mut entries).map_err(|e| {
+                        io::Error::new(e.kind(), format!("Level {}: {}", entry.id, e))
+                    })?;
+                }
+                continue;
+            }
+
+            let namespace = section.namespace();
+            let dir = match namespace {
+                Some(namespace) => format!("{}/", namespace_directory(namespace)),
+                None => String::new(),
+            };
+            let mut dirs = vec![];
+            lump_directories(root, &dir, &mut dirs)?;
+
+            if let Some(namespace) = namespace {
+                entries.push(marker(namespace, "_START"));
+            }
+
+            for entry in section_entries {
+                let path = if entry.same_as_previous {
+                    entries.last().and_then(|previous| previous.path.clone())
+                } else {
+                    let path = dirs.iter().find_map(|dir| {
+                        let path = unique_lump_path(&mut used, dir, &entry.id);
+                        if root.join(&path).is_file() {
+                            Some(path)
+                        } else {
+                            used.remove(&path.to_ascii_lowercase());
+                            None
+                        }
+                    });
+                    if path.is_none() && !section.allows_empty() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!(
+                                "Lump file for {} in [{}] not found: {}{}.lmp",
+                                entry.id.escaped(),
+                                section.name(),
+                                dir,
+                                lump_file_name(&entry.id)
+                            ),
+                        ));
+                    }
+                    path
+                };
+                entries.push(ManifestEntry { id: entry.id, path });
+            }
+
+            if let Some(namespace) = namespace {
+                entries.push(marker(namespace, "_END"));
+            }
+        }
+
+        Ok(Manifest { kind, entries })
+    }
+}
+
+impl std::str::FromStr for WadInfo {
+    type Err = WadInfoError;
+
+    fn from_str(s: &str) -> Result<WadInfo, WadInfoError> {
+        WadInfo::parse(s)
+    }
+}
+
+impl std::fmt::Display for WadInfo {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, (section, entries)) in self.sections.iter().enumerate() {
+            if i > 0 {
+                writeln!(fmt)?;
+            }
+            writeln!(fmt, "[{}]", section.name())?;
+            for entry in entries {
+                write!(fmt, "{}", entry.id.escaped())?;
+                if entry.same_as_previous {
+                    write!(fmt, " *")?;
+                } else if let Some((x, y)) = entry.offset {
+                    write!(fmt, " {} {}", x, y)?;
+                }
+                writeln!(fmt)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    fn sample() -> Vec<u8> {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        for (name, lump) in &[
+            ("E1M1", ""),
+            ("THINGS", "t"),
+            ("LINEDEFS", "l"),
+            ("PLAYPAL", "pal"),
+            ("DSPISTOL", "pistol"),
+            ("D_E1M1", "music"),
+            ("TEXTURE1", "tex"),
+            ("S_START", ""),
+            ("TROOA1", "troo"),
+            ("TROOA2", "troo2"),
+            ("S_END", ""),
+            ("FF_START", ""),
+            ("FLOOR0_1", "floor"),
+            ("FF_END", ""),
+        ] {
+            builder.push(EntryId::from_str(name).unwrap(), lump.as_bytes());
+        }
+        builder.to_bytes().unwrap()
+    }
+
+    fn names(entries: &[WadInfoEntry]) -> Vec<String> {
+        entries.iter().map(|e| e.id.to_string()).collect()
+    }
+
+    #[test]
+    fn sorts_entries_into_sections() {
+        let wad = parse_wad(sample()).unwrap();
+        let wadinfo = WadInfo::from_wad(&wad.as_slice());
+
+        let sections: Vec<_> = wadinfo
+            .sections
+            .iter()
+            .map(|(section, entries)| (section.name(), names(entries)))
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                ("levels", vec!["E1M1".to_string()]),
+                ("lumps", vec!["PLAYPAL".to_string()]),
+                ("sounds", vec!["DSPISTOL".to_string()]),
+                ("musics", vec!["D_E1M1".to_string()]),
+                ("textures", vec!["TEXTURE1".to_string()]),
+                ("sprites", vec!["TROOA1".to_string(), "TROOA2".to_string()]),
+                ("flats", vec!["FLOOR0_1".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_and_emits() {
+        let text = "\
+            # A comment\n\
+            [levels]\n\
+            E1M1\n\
+            \n\
+            [Sprites]\n\
+            TROOA1 19 -57\n\
+            TROOA2 *\n\
+            [pnames]\n\
+            PNAMES\n";
+        let wadinfo = WadInfo::parse(text).unwrap();
+
+        assert_eq!(wadinfo.sections.len(), 3);
+        assert_eq!(wadinfo.sections[1].0, Section::Sprites);
+        assert_eq!(wadinfo.sections[1].1[0].offset, Some((19, -57)));
+        assert!(wadinfo.sections[1].1[1].same_as_previous);
+        assert_eq!(wadinfo.sections[2].0, Section::Other("pnames".to_string()));
+
+        let emitted = wadinfo.to_string();
+        assert_eq!(
+            emitted,
+            "[levels]\nE1M1\n\n[sprites]\nTROOA1 19 -57\nTROOA2 *\n\n[pnames]\nPNAMES\n"
+        );
+        assert_eq!(WadInfo::parse(&emitted).unwrap(), wadinfo);
+    }
+
+    #[test]
+    fn rejects_invalid_wadinfo() {
+        assert!(matches!(
+            WadInfo::parse("PLAYPAL\n"),
+            Err(WadInfoError::EntryOutsideSection { line: 1 })
+        ));
+        assert!(matches!(
+            WadInfo::parse("[lumps]\nPLAYPAL 1\n"),
+            Err(WadInfoError::InvalidLine { line: 2, .. })
+        ));
+        assert!(matches!(
+            WadInfo::parse("[lumps]\nTOOLONGNAME\n"),
+            Err(WadInfoError::InvalidName { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn maps_to_manifest() {
+        let data = sample();
+        let wad = parse_wad(data).unwrap();
+
+        let root = std::env::temp_dir().join(format!("wad-wadinfo-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let manifest = Manifest::new(Kind::PWad, &wad.as_slice()).unwrap();
+        for (index, entry) in manifest.entries.iter().enumerate() {
+            if let Some(path) = &entry.path {
+                let path = root.join(path);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, wad.lump(index).unwrap()).unwrap();
+            }
+        }
+
+        let wadinfo = WadInfo::from_wad(&wad.as_slice());
+        let mapped = WadInfo::parse(&wadinfo.to_string())
+            .unwrap()
+            .to_manifest(Kind::PWad, &root)
+            .unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let entries: Vec<_> = mapped
+            .entries
+            .iter()
+            .map(|e| (e.id.to_string(), e.path.as_deref()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("E1M1".to_string(), None),
+                ("THINGS".to_string(), Some("maps/E1M1/THINGS.lmp")),
+                ("LINEDEFS".to_string(), Some("maps/E1M1/LINEDEFS.lmp")),
+                ("PLAYPAL".to_string(), Some("PLAYPAL.lmp")),
+                ("DSPISTOL".to_string(), Some("DSPISTOL.lmp")),
+                ("D_E1M1".to_string(), Some("D_E1M1.lmp")),
+                ("TEXTURE1".to_string(), Some("TEXTURE1.lmp")),
+                ("S_START".to_string(), None),
+                ("TROOA1".to_string(), Some("sprites/TROOA1.lmp")),
+                ("TROOA2".to_string(), Some("sprites/TROOA2.lmp")),
+                ("S_END".to_string(), None),
+                ("F_START".to_string(), None),
+                ("FLOOR0_1".to_string(), Some("flats/FLOOR0_1.lmp")),
+                ("F_END".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn roundtrips_nested_namespaces_and_empty_lumps() {
+        let mut builder = WadBuilder::new(Kind::PWad);
+        for (name, lump) in &[
+            ("F_START", ""),
+            ("F1_START", ""),
+            ("FLOOR1", "floor"),
+            ("F1_END", ""),
+            ("F_END", ""),
+            ("TX_START", ""),
+            ("WALL", "wall"),
+            ("TX_END", ""),
+            ("EMPTY", ""),
+        ] {
+            builder.push(EntryId::from_str(name).unwrap(), lump.as_bytes());
+        }
+        let wad = parse_wad(builder.to_bytes().unwrap()).unwrap();
+
+        let root = std::env::temp_dir().join(format!("wad-wadinfo-nested-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let manifest = Manifest::new(Kind::PWad, &wad.as_slice()).unwrap();
+        for (index, entry) in manifest.entries.iter().enumerate() {
+            if let Some(path) = &entry.path {
+                let path = root.join(path);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, wad.lump(index).unwrap()).unwrap();
+            }
+        }
+
+        let wadinfo = WadInfo::from_wad(&wad.as_slice());
+        let packed = WadInfo::parse(&wadinfo.to_string())
+            .unwrap()
+            .to_manifest(Kind::PWad, &root)
+            .and_then(|manifest| manifest.to_builder(&root))
+            .map(|builder| parse_wad(builder.to_bytes().unwrap()).unwrap());
+        std::fs::remove_dir_all(&root).unwrap();
+
+        // The nested markers are not listed in wadinfo.txt, as with DeuTex
+        let entries: Vec<_> = packed
+            .unwrap()
+            .entry_iter()
+            .map(|e| (e.id.to_string(), e.lump.to_vec()))
+            .collect();
+        let expected: Vec<_> = [
+            ("TX_START", ""),
+            ("WALL", "wall"),
+            ("TX_END", ""),
+            ("EMPTY", ""),
+            ("F_START", ""),
+            ("FLOOR1", "floor"),
+            ("F_END", ""),
+        ]
+        .iter()
+        .map(|(name, lump)| (name.to_string(), lump.as_bytes().to_vec()))
+        .collect();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn missing_lump_file_is_an_error() {
+        let root = std::env::temp_dir().join(format!("wad-wadinfo-missing-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("PLAYPAL.lmp"), b"pal").unwrap();
+
+        let mapped = |text: &str| WadInfo::parse(text).unwrap().to_manifest(Kind::PWad, &root);
+        let empty = mapped("[lumps]\nPLAYPAL\nG_START\nEMPTY\nG_END\n");
+        let typo = mapped("[sprites]\nTROOA1\n");
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let paths: Vec<_> = empty.unwrap().entries.into_iter().map(|e| e.path).collect();
+        assert_eq!(paths, [Some("PLAYPAL.lmp".to_string()), None, None, None]);
+
+        let err = typo.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("sprites/TROOA1.lmp"), "{}", err);
+    }
+}